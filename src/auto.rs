@@ -0,0 +1,139 @@
+use crate::{ScriptError, commits, json_node_extra::*, script_error};
+use json5_nodes::JsonNode;
+use std::path::Path;
+
+/// The operation that chooses another operation from the commit history.
+pub(crate) const OPERATION: &str = "auto";
+
+/// The levels of version bump, in increasing order, with the `auto` entry naming the operation for each.
+const BUMPS: [&str; 3] = ["patch", "minor", "major"];
+
+/// Validate the `auto` section of a script, if there is one.
+pub(crate) fn validate(root_node: &JsonNode) -> Result<(), ScriptError> {
+    let Ok(auto_node) = root_node.get_object_entry("auto") else {
+        return Ok(());
+    };
+
+    if !auto_node.is_object() {
+        return Err(script_error!("'auto' must be an object", auto_node));
+    }
+
+    let operations_node = root_node.get_object_entry("operations")?;
+
+    for bump in BUMPS {
+        let operation_node = auto_node.get_object_entry(bump)?;
+
+        if !operation_node.is_string()
+            || operations_node
+                .get_object_entry(&operation_node.get_string())
+                .is_err()
+        {
+            return Err(script_error!(
+                format!("'{}' must name an entry in 'operations'", bump),
+                operation_node
+            ));
+        }
+    }
+
+    if let Ok(tag_match_node) = auto_node.get_object_entry("tagMatch")
+        && !tag_match_node.is_string()
+    {
+        return Err(script_error!("'tagMatch' must be a string", tag_match_node));
+    }
+
+    Ok(())
+}
+
+/// Check that an `operations` entry does not have the name of the built-in operation when there is an
+/// `auto` section.
+pub(crate) fn validate_operation_name(
+    root_node: &JsonNode,
+    name: &str,
+    operation_node: &JsonNode,
+) -> Result<(), ScriptError> {
+    if name == OPERATION && root_node.get_object_entry("auto").is_ok() {
+        return Err(script_error!(
+            format!(
+                "Operation '{}' is built in when there is an 'auto' section",
+                OPERATION
+            ),
+            operation_node
+        ));
+    }
+
+    Ok(())
+}
+
+/// Choose the operation to run from the Conventional Commits since the last tag, explaining the decision.
+///
+/// A breaking change chooses the `major` operation, a `feat` commit the `minor` operation and a `fix` or
+/// `perf` commit the `patch` operation. It is an error if no commits need a release.
+pub(crate) fn choose_operation(
+    repo_dir: &Path,
+    root_node: &JsonNode,
+) -> Result<String, ScriptError> {
+    let auto_node = root_node.get_object_entry("auto").map_err(|_| {
+        script_error!(
+            format!(
+                "Operation '{}' requires an 'auto' section in the script file",
+                OPERATION
+            ),
+            root_node
+        )
+    })?;
+    let tag_match = auto_node
+        .get_object_entry("tagMatch")
+        .ok()
+        .map(|node| node.get_string());
+    let (tag, commits) = commits::commits_since_last_tag(repo_dir, tag_match.as_deref())
+        .map_err(|message| script_error!(message, auto_node))?;
+    let since = match &tag {
+        Some(tag) => format!("since tag '{}'", tag),
+        None => "with no previous tag".to_string(),
+    };
+    let mut chosen_bump = None;
+
+    for commit in commits.iter() {
+        let bump = if commit.breaking {
+            Some(2)
+        } else {
+            match commit.kind.as_deref() {
+                Some("feat") => Some(1),
+                Some("fix" | "perf") => Some(0),
+                _ => None,
+            }
+        };
+
+        if let Some(bump) = bump {
+            log::info!(
+                "Commit {} '{}' needs a {} release",
+                commit.short_sha(),
+                commit.description,
+                BUMPS[bump]
+            );
+            chosen_bump = chosen_bump.max(Some(bump));
+        }
+    }
+
+    let Some(bump) = chosen_bump else {
+        return Err(script_error!(
+            format!(
+                "None of the {} commit(s) {} are a feature, fix or breaking change, so there is nothing to release",
+                commits.len(),
+                since
+            ),
+            auto_node
+        ));
+    };
+    let operation = auto_node.get_object_entry(BUMPS[bump])?.get_string();
+
+    log::info!(
+        "Chose a {} release with operation '{}' from {} commit(s) {}",
+        BUMPS[bump],
+        operation,
+        commits.len(),
+        since
+    );
+
+    Ok(operation)
+}
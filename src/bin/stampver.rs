@@ -1,16 +1,28 @@
 use anyhow::Context;
 use clap::Parser;
 use env_logger::{Builder, Target};
+use evalexpr::Value;
+use jiff::{Timestamp, tz::TimeZone};
+use json5_nodes::JsonNode;
 use log::{Level, LevelFilter};
-use stampver::{ScriptError, StampVerTool};
-use std::{io::Write, path::PathBuf, process::exit};
+use stampver::{
+    AUTO_OPERATION, CHECK_OPERATION, FileReport, RunReport, ScriptError, StampVerTool, Transaction,
+};
+use std::{
+    env, fs,
+    io::Write,
+    path::{Path, PathBuf},
+    process::exit,
+};
 
 #[derive(clap::Parser)]
 #[command(version, about, long_about = None)]
 struct Cli {
-    /// The versioning operation to perform
+    /// The versioning operations to perform in order, each followed by its arguments, e.g. the channel for
+    /// 'nextPrerelease'. Use 'auto' to choose an operation from the commit history, or 'check' alone to verify
+    /// that every target file is in sync
     #[arg(value_name = "OPERATION")]
-    operation: Option<String>,
+    operations: Vec<String>,
 
     /// Specify the version file explicitly
     #[arg(
@@ -28,6 +40,50 @@ struct Cli {
     /// Filter output to update only files under certain directories
     #[arg(value_name = "DIR_PATH", short = 'f', long = "filter")]
     filter_path: Vec<PathBuf>,
+
+    /// Print a unified diff of the changes to each file
+    #[arg(short, long)]
+    diff: bool,
+
+    /// Write a unified diff of all the changes to a patch file
+    #[arg(value_name = "PATCH_FILE", short, long = "patch")]
+    patch_file: Option<PathBuf>,
+
+    /// Commit the changed files with the message from the 'release' section, requires --update
+    #[arg(long, requires = "update")]
+    commit: bool,
+
+    /// Create an annotated tag from the 'release' section, requires --commit
+    #[arg(long, requires = "commit")]
+    tag: bool,
+
+    /// Use a fixed RFC 3339 time for the now:: values, overriding SOURCE_DATE_EPOCH
+    #[arg(value_name = "TIME", long)]
+    now: Option<Timestamp>,
+
+    /// Use an IANA time zone for the now:: values instead of the 'tz' var
+    #[arg(value_name = "TIME_ZONE", long)]
+    tz: Option<String>,
+
+    /// Override a var before the operation runs, converting the value to the type of the var
+    #[arg(value_name = "NAME=VALUE", long = "set")]
+    set: Vec<String>,
+
+    /// Override a var with a string value
+    #[arg(value_name = "NAME=VALUE", long = "set-str")]
+    set_str: Vec<String>,
+
+    /// Override a var with an integer value
+    #[arg(value_name = "NAME=VALUE", long = "set-int")]
+    set_int: Vec<String>,
+
+    /// Save the vars given with --set, --set-str and --set-int to the script file
+    #[arg(long)]
+    persist_set: bool,
+
+    /// Run only this component of the script file, can be repeated, otherwise every component is run
+    #[arg(value_name = "NAME", long = "component")]
+    component: Vec<String>,
 }
 
 fn main() {
@@ -67,10 +123,63 @@ pub fn run() -> anyhow::Result<i32> {
         .target(Target::Stderr)
         .init();
 
-    let tool = StampVerTool::new();
+    let mut tool = StampVerTool::new();
+
+    if let Some(now) = cli.now {
+        tool = tool.with_now(now);
+    } else if let Ok(source_date_epoch) = env::var("SOURCE_DATE_EPOCH") {
+        let now = source_date_epoch
+            .trim()
+            .parse()
+            .ok()
+            .and_then(|seconds| Timestamp::from_second(seconds).ok())
+            .context(format!(
+                "SOURCE_DATE_EPOCH '{}' is not a valid Unix timestamp",
+                source_date_epoch
+            ))?;
+
+        log::info!("Using SOURCE_DATE_EPOCH time '{}'", now);
+        tool = tool.with_now(now);
+    }
+
+    if let Some(tz) = &cli.tz {
+        tool =
+            tool.with_time_zone(TimeZone::get(tz).context(format!("unknown time zone '{}'", tz))?);
+    }
+
     let (content, root_node, script_file) = tool
-        .read_script_file(cli.input_file)
+        .read_script_file(cli.input_file.clone())
         .context("failed to read script file")?;
+
+    let component_root_nodes = component_root_nodes(&tool, &root_node, &cli.component)
+        .map_err(|e| with_script_file(e, &script_file))?;
+
+    for (option, name, text) in var_overrides(&cli)? {
+        tool = match option {
+            "--set" => tool.with_var_override_text(name, text),
+            "--set-int" => tool.with_var_override(
+                name,
+                Value::Int(text.parse().context(format!(
+                    "'{}' value '{}' for var '{}' is not an integer",
+                    option, text, name
+                ))?),
+            ),
+            _ => tool.with_var_override(name, Value::from(text)),
+        };
+    }
+
+    tool.check_var_overrides(
+        &component_root_nodes
+            .iter()
+            .map(|(_, root_node)| root_node)
+            .collect::<Vec<_>>(),
+    )
+    .map_err(|e| with_script_file(e, &script_file))?;
+
+    if cli.persist_set {
+        tool = tool.with_persisted_var_overrides();
+    }
+
     let filter_paths = tool.validate_filter_paths(&cli.filter_path, &script_file)?;
 
     for filter_path in filter_paths.iter() {
@@ -80,25 +189,308 @@ pub fn run() -> anyhow::Result<i32> {
         );
     }
 
+    if cli.operations.first().map(String::as_str) == Some(CHECK_OPERATION) {
+        if cli.update {
+            anyhow::bail!("'--update' cannot be used with '{}'", CHECK_OPERATION);
+        }
+
+        if cli.operations.len() > 1 {
+            anyhow::bail!(
+                "'{}' cannot be combined with other operations",
+                CHECK_OPERATION
+            );
+        }
+
+        let run_report = (|| {
+            let mut run_report = RunReport::default();
+
+            for (component, root_node) in component_root_nodes.iter() {
+                log_component(component);
+                tool.validate_script_file(root_node)?;
+
+                let mut run_context = tool.create_run_context(root_node)?;
+
+                run_report.targets.extend(
+                    tool.check_targets(&script_file, root_node, &mut run_context, &filter_paths)?
+                        .targets,
+                );
+            }
+
+            Ok::<_, ScriptError>(run_report)
+        })()
+        .map_err(|e| with_script_file(e, &script_file))?;
+
+        return Ok(check_run_report(&run_report, &script_file, cli.diff));
+    }
+
     let inner_run = || {
-        tool.validate_script_file(&root_node)?;
+        let mut transaction = Transaction::new();
+        let mut run_report = RunReport::default();
+        let mut runs = vec![];
+
+        for (component, root_node) in component_root_nodes.iter() {
+            log_component(component);
+            tool.validate_script_file(root_node)?;
+
+            let mut run_context = tool.create_run_context(root_node)?;
+            let mut operations = tool.parse_operations(root_node, &cli.operations)?;
 
-        let mut run_context = tool.create_run_context(&root_node)?;
+            for (operation, _) in operations.iter_mut() {
+                if operation == AUTO_OPERATION {
+                    *operation = tool.choose_auto_operation(&script_file, root_node)?;
+                }
+            }
+
+            tool.run_operations(&operations, root_node, &mut run_context)?;
+            run_report.targets.extend(
+                tool.stage_targets(
+                    &script_file,
+                    root_node,
+                    &mut run_context,
+                    &filter_paths,
+                    &mut transaction,
+                )?
+                .targets,
+            );
+            runs.push((root_node, run_context));
+        }
 
-        tool.run_operation(cli.operation, &root_node, &mut run_context)?;
-        tool.process_targets(
+        let script_reports = tool.stage_script_files(
             &script_file,
-            &root_node,
-            cli.update,
-            &mut run_context,
-            &filter_paths,
+            content,
+            &runs
+                .iter()
+                .map(|(root_node, run_context)| (*root_node, run_context))
+                .collect::<Vec<_>>(),
+            &mut transaction,
         )?;
-        tool.update_script_file(&script_file, content, &root_node, &run_context, cli.update)?;
 
-        Ok::<_, ScriptError>(())
+        let release = if cli.commit {
+            let [(root_node, run_context)] = runs.as_slice() else {
+                return Err(ScriptError::new(
+                    "'--commit' can only be used with one component, choose it with '--component'"
+                        .to_string(),
+                    None,
+                    None,
+                ));
+            };
+
+            Some(
+                tool.evaluate_release(root_node, run_context)?
+                    .ok_or_else(|| {
+                        ScriptError::new(
+                            "'--commit' requires a 'release' section in the script file"
+                                .to_string(),
+                            None,
+                            None,
+                        )
+                    })?,
+            )
+        } else {
+            None
+        };
+
+        Ok::<_, ScriptError>((run_report, script_reports, release, transaction))
     };
 
-    inner_run().map_err(|e| ScriptError::new(e.message, Some(script_file.clone()), e.location))?;
+    let (mut run_report, mut script_reports, release, transaction) =
+        inner_run().map_err(|e| with_script_file(e, &script_file))?;
+
+    if cli.update {
+        if cli.tag
+            && let Some(release) = &release
+        {
+            tool.check_release_tag(&script_file, release)?;
+        }
+
+        let changed_paths: Vec<PathBuf> = transaction
+            .changed_paths()
+            .map(|path| path.to_path_buf())
+            .collect();
+
+        transaction
+            .commit()
+            .map_err(|e| with_script_file(e, &script_file))?;
+        run_report.mark_applied();
+
+        for script_report in script_reports.iter_mut() {
+            script_report.applied = true;
+        }
+
+        if let Some(release) = &release {
+            tool.commit_release(&script_file, release, &changed_paths, cli.tag)?;
+        }
+    }
+
+    log_run_report(&run_report, &script_reports);
+
+    let patch = script_reports
+        .iter()
+        .fold(run_report.patch(), |patch, script_report| {
+            patch + script_report.diff.as_deref().unwrap_or_default()
+        });
+
+    if cli.diff {
+        print!("{}", patch);
+    }
+
+    if let Some(patch_file) = cli.patch_file {
+        fs::write(&patch_file, &patch).context(format!(
+            "failed to write patch file '{}'",
+            patch_file.display()
+        ))?;
+        log::info!("Wrote patch to '{}'", patch_file.display());
+    }
 
     Ok(0)
 }
+
+/// Get the root node of each component to run, in the order given, or of the script file if it has no
+/// components.
+fn component_root_nodes(
+    tool: &StampVerTool,
+    root_node: &JsonNode,
+    names: &[String],
+) -> Result<Vec<(Option<String>, JsonNode)>, ScriptError> {
+    let component_names = tool.component_names(root_node)?;
+
+    if component_names.is_empty() {
+        if let Some(name) = names.first() {
+            return Err(ScriptError::new(
+                format!(
+                    "'--component {}' cannot be used because the script file has no 'components'",
+                    name
+                ),
+                None,
+                None,
+            ));
+        }
+
+        return Ok(vec![(None, root_node.clone())]);
+    }
+
+    let names = if names.is_empty() {
+        &component_names
+    } else {
+        names
+    };
+
+    names
+        .iter()
+        .map(|name| {
+            Ok((
+                Some(name.clone()),
+                tool.component_root_node(root_node, name)?,
+            ))
+        })
+        .collect()
+}
+
+/// Set the script file of an error that does not come from a file that the script `extends` or `include`s.
+fn with_script_file(error: ScriptError, script_file: &Path) -> ScriptError {
+    let script_file = error
+        .script_file
+        .unwrap_or_else(|| script_file.to_path_buf());
+
+    ScriptError::new(error.message, Some(script_file), error.location)
+}
+
+/// Log the component that is about to run, if the script file has components.
+fn log_component(component: &Option<String>) {
+    if let Some(component) = component {
+        log::info!("Component '{}'", component);
+    }
+}
+
+/// Split the `--set`, `--set-str` and `--set-int` options into the option, var name and value text.
+fn var_overrides(cli: &Cli) -> anyhow::Result<Vec<(&'static str, &str, &str)>> {
+    let mut overrides = vec![];
+
+    for (option, values) in [
+        ("--set", &cli.set),
+        ("--set-str", &cli.set_str),
+        ("--set-int", &cli.set_int),
+    ] {
+        for value in values.iter() {
+            let (name, text) = value
+                .split_once('=')
+                .context(format!("'{}' expects NAME=VALUE, not '{}'", option, value))?;
+
+            overrides.push((option, name, text));
+        }
+    }
+
+    Ok(overrides)
+}
+
+/// Log each file that is out of sync with the script file, returning the exit code.
+fn check_run_report(run_report: &RunReport, script_file: &Path, diff: bool) -> i32 {
+    let mut out_of_sync_count = 0;
+
+    for target_report in run_report.targets.iter() {
+        for file_report in target_report.files.iter() {
+            if let Some(reason) = file_report.out_of_sync_reason() {
+                log::error!(
+                    "'{}' -> '{}' is out of sync with '{}' - {}",
+                    target_report.description,
+                    file_report.path.display(),
+                    script_file.display(),
+                    reason
+                );
+                out_of_sync_count += 1;
+            }
+        }
+    }
+
+    if diff {
+        print!("{}", run_report.patch());
+    }
+
+    if out_of_sync_count == 0 {
+        log::info!(
+            "All {} target file(s) are in sync",
+            run_report.files().count()
+        );
+        0
+    } else {
+        log::error!("{} target file(s) are out of sync", out_of_sync_count);
+        1
+    }
+}
+
+/// Log a description of each file processed in the run, followed by each script file whose vars changed.
+fn log_run_report(run_report: &RunReport, script_reports: &[FileReport]) {
+    for target_report in run_report.targets.iter() {
+        for file_report in target_report.files.iter() {
+            log::info!(
+                "{} '{}' -> '{}'",
+                file_report.action.describe(file_report.applied),
+                target_report.description,
+                file_report.path.display()
+            );
+
+            for update_report in file_report.updates.iter() {
+                match &update_report.skip_reason {
+                    Some(skip_reason) => log::info!(
+                        "  Skipped '{}' because {}",
+                        update_report.search,
+                        skip_reason
+                    ),
+                    None => log::info!(
+                        "  {} match(es) for '{}'",
+                        update_report.match_count,
+                        update_report.search
+                    ),
+                }
+            }
+        }
+    }
+
+    for script_report in script_reports.iter().filter(|report| report.is_changed()) {
+        log::info!(
+            "{} 'vars' -> '{}'",
+            script_report.action.describe(script_report.applied),
+            script_report.path.display()
+        );
+    }
+}
@@ -0,0 +1,221 @@
+use crate::{ScriptError, commits, json_node_extra::*, script_error};
+use evalexpr::{ContextWithMutableVariables, HashMapContext, Value};
+use json5_nodes::JsonNode;
+use std::path::Path;
+
+/// The heading expression used when a `changelog` entry does not give one.
+const DEFAULT_HEADING: &str = r###""## " + version + " - " + now::format("%Y-%m-%d")"###;
+
+/// The entry template used when a `changelog` entry does not give one.
+const DEFAULT_ENTRY: &str = "- {scope}{description} ({shortSha})";
+
+/// The commit types listed when a `changelog` entry does not give them, in order, with their titles.
+const DEFAULT_TYPES: [(&str, &str); 4] = [
+    ("breaking", "Breaking Changes"),
+    ("feat", "Features"),
+    ("fix", "Bug Fixes"),
+    ("perf", "Performance Improvements"),
+];
+
+/// Validate the `changelog` entry of a target.
+pub(crate) fn validate(changelog_node: &JsonNode) -> Result<(), ScriptError> {
+    if !changelog_node.is_object() {
+        return Err(script_error!(
+            "'changelog' must be an object",
+            changelog_node
+        ));
+    }
+
+    let version_node = changelog_node.get_object_entry("version")?;
+
+    if !version_node.is_string() {
+        return Err(script_error!(
+            "'version' entry must be string",
+            version_node
+        ));
+    }
+
+    for entry in ["heading", "entry", "tagMatch"] {
+        if let Ok(node) = changelog_node.get_object_entry(entry)
+            && !node.is_string()
+        {
+            return Err(script_error!(
+                format!("'{}' entry must be string", entry),
+                node
+            ));
+        }
+    }
+
+    if let Ok(types_node) = changelog_node.get_object_entry("types") {
+        for (kind, title_node) in types_node.get_object_iter()? {
+            if !title_node.is_string() {
+                return Err(script_error!(
+                    format!("'types' entry '{}' must be a string", kind),
+                    title_node
+                ));
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Apply the `changelog` entry of a target to the content of a Markdown changelog, which may be empty.
+///
+/// A section for the version is inserted before the first section at the same heading level, or appended if
+/// there is none. The section lists the commits since the last tag in the repository, grouped by
+/// Conventional Commit type. If a heading at that level already contains the version the content is
+/// returned unchanged, so running again for the same version does not add another section.
+pub(crate) fn update_changelog(
+    repo_dir: &Path,
+    content: &str,
+    changelog_node: &JsonNode,
+    context: &HashMapContext,
+) -> Result<String, ScriptError> {
+    let version_node = changelog_node.get_object_entry("version")?;
+    let version = evalexpr::eval_string_with_context(&version_node.get_string(), context)
+        .map_err(|e| script_error!(e.to_string(), version_node))?;
+
+    if version.is_empty() {
+        return Err(script_error!("'version' must not be empty", version_node));
+    }
+
+    let mut heading_context = context.clone();
+
+    heading_context.set_value("version".to_owned(), Value::from(version.as_str()))?;
+
+    let heading_node = changelog_node.get_object_entry("heading").ok();
+    let heading = evalexpr::eval_string_with_context(
+        &heading_node.map_or(DEFAULT_HEADING.to_string(), |node| node.get_string()),
+        &heading_context,
+    )
+    .map_err(|e| script_error!(e.to_string(), heading_node.unwrap_or(changelog_node)))?;
+    let level = heading_level(&heading).ok_or_else(|| {
+        script_error!(
+            format!("Changelog heading '{}' is not a Markdown heading", heading),
+            heading_node.unwrap_or(changelog_node)
+        )
+    })?;
+    let lines: Vec<&str> = content.lines().collect();
+
+    if lines
+        .iter()
+        .any(|line| heading_level(line) == Some(level) && contains_version(line, &version))
+    {
+        log::info!("Changelog already has a section for version '{}'", version);
+        return Ok(content.to_string());
+    }
+
+    let tag_match = changelog_node
+        .get_object_entry("tagMatch")
+        .ok()
+        .map(|node| node.get_string());
+    let (_, commits) = commits::commits_since_last_tag(repo_dir, tag_match.as_deref())
+        .map_err(|message| script_error!(message, changelog_node))?;
+    let types: Vec<(String, String)> = match changelog_node.get_object_entry("types") {
+        Ok(types_node) => types_node
+            .get_object_iter()?
+            .map(|(kind, title_node)| (kind.to_string(), title_node.get_string()))
+            .collect(),
+        Err(_) => DEFAULT_TYPES
+            .iter()
+            .map(|(kind, title)| (kind.to_string(), title.to_string()))
+            .collect(),
+    };
+    let entry_template = changelog_node
+        .get_object_entry("entry")
+        .map_or(DEFAULT_ENTRY.to_string(), |node| node.get_string());
+    let separate_breaking = types.iter().any(|(kind, _)| kind == "breaking");
+    let mut section = vec![heading.clone()];
+
+    for (kind, title) in types.iter() {
+        let entries: Vec<String> = commits
+            .iter()
+            .filter(|commit| {
+                if kind == "breaking" {
+                    commit.breaking
+                } else {
+                    commit.kind.as_deref() == Some(kind.as_str())
+                        && !(separate_breaking && commit.breaking)
+                }
+            })
+            .map(|commit| render_entry(&entry_template, commit))
+            .collect();
+
+        if !entries.is_empty() {
+            section.push(String::new());
+            section.push(format!("{} {}", "#".repeat(level + 1), title));
+            section.push(String::new());
+            section.extend(entries);
+        }
+    }
+
+    log::info!(
+        "Changelog section '{}' lists {} commit(s)",
+        heading,
+        commits.len()
+    );
+
+    let insert_index = lines
+        .iter()
+        .position(|line| heading_level(line) == Some(level));
+    let mut new_lines: Vec<String> = vec![];
+
+    match insert_index {
+        Some(index) => {
+            new_lines.extend(lines[..index].iter().map(|line| line.to_string()));
+            new_lines.extend(section);
+            new_lines.push(String::new());
+            new_lines.extend(lines[index..].iter().map(|line| line.to_string()));
+        }
+        None => {
+            new_lines.extend(lines.iter().map(|line| line.to_string()));
+
+            if new_lines.last().is_some_and(|line| !line.is_empty()) {
+                new_lines.push(String::new());
+            }
+
+            new_lines.extend(section);
+        }
+    }
+
+    Ok(new_lines.join("\n") + "\n")
+}
+
+/// The level of a Markdown ATX heading such as `## 1.0.0`, or `None` if the line is not a heading.
+fn heading_level(line: &str) -> Option<usize> {
+    let level = line.chars().take_while(|c| *c == '#').count();
+
+    (1..=6)
+        .contains(&level)
+        .then_some(level)
+        .filter(|_| line[level..].starts_with(' '))
+}
+
+/// Does a heading contain the version as a whole word, so `1.2.3` does not match `1.2.30`?
+fn contains_version(line: &str, version: &str) -> bool {
+    let is_version_char = |c: char| c.is_ascii_alphanumeric() || c == '.' || c == '-';
+
+    line.match_indices(version).any(|(index, _)| {
+        !line[..index].ends_with(is_version_char)
+            && !line[index + version.len()..].starts_with(is_version_char)
+    })
+}
+
+/// Render a commit with an entry template, replacing `{type}`, `{scope}`, `{shortSha}`, `{sha}` and
+/// `{description}`. A scope is rendered in bold followed by a colon, or as nothing if there is no scope.
+fn render_entry(template: &str, commit: &commits::Commit) -> String {
+    template
+        .replace("{type}", commit.kind.as_deref().unwrap_or_default())
+        .replace(
+            "{scope}",
+            &commit
+                .scope
+                .as_ref()
+                .map(|scope| format!("**{}:** ", scope))
+                .unwrap_or_default(),
+        )
+        .replace("{shortSha}", commit.short_sha())
+        .replace("{sha}", &commit.sha)
+        .replace("{description}", &commit.description)
+}
@@ -0,0 +1,94 @@
+use crate::git;
+use regex::Regex;
+use std::{path::Path, sync::LazyLock};
+
+/// The header of a Conventional Commit, e.g. `feat(parser)!: Add arrays`.
+static HEADER_RE: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(
+        r"^(?P<type>[A-Za-z]+)(?:\((?P<scope>[^()]*)\))?(?P<breaking>!)?: *(?P<description>.+)$",
+    )
+    .unwrap()
+});
+
+/// A breaking change footer in the body of a Conventional Commit.
+static BREAKING_RE: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"(?m)^BREAKING[ -]CHANGE: ").unwrap());
+
+#[derive(Clone, Debug, PartialEq)]
+/// A commit, parsed as a Conventional Commit if possible.
+pub(crate) struct Commit {
+    pub sha: String,
+    /// The lowercase commit type, e.g. `feat` or `fix`, or `None` if it is not a Conventional Commit.
+    pub kind: Option<String>,
+    pub scope: Option<String>,
+    /// The commit description, or the whole first line if it is not a Conventional Commit.
+    pub description: String,
+    pub breaking: bool,
+}
+
+impl Commit {
+    /// Parse a commit from its hash and full message.
+    pub fn parse(sha: &str, message: &str) -> Commit {
+        let header = message.lines().next().unwrap_or_default().trim();
+        let breaking_footer = BREAKING_RE.is_match(message);
+
+        match HEADER_RE.captures(header) {
+            Some(captures) => Commit {
+                sha: sha.to_string(),
+                kind: Some(captures["type"].to_lowercase()),
+                scope: captures
+                    .name("scope")
+                    .map(|scope| scope.as_str().to_string()),
+                description: captures["description"].trim().to_string(),
+                breaking: captures.name("breaking").is_some() || breaking_footer,
+            },
+            None => Commit {
+                sha: sha.to_string(),
+                kind: None,
+                scope: None,
+                description: header.to_string(),
+                breaking: breaking_footer,
+            },
+        }
+    }
+
+    /// The abbreviated commit hash.
+    pub fn short_sha(&self) -> &str {
+        &self.sha[..self.sha.len().min(7)]
+    }
+}
+
+/// Get the most recent tag reachable from `HEAD`, optionally matching a glob pattern, and the commits since
+/// that tag, excluding merges, newest first. All commits are returned if there is no tag.
+pub(crate) fn commits_since_last_tag(
+    repo_dir: &Path,
+    tag_match: Option<&str>,
+) -> Result<(Option<String>, Vec<Commit>), String> {
+    let mut describe_args = vec!["describe", "--tags", "--abbrev=0"];
+
+    if let Some(tag_match) = tag_match {
+        describe_args.extend(["--match", tag_match]);
+    }
+
+    git::run(repo_dir, &["rev-parse", "HEAD"])?;
+
+    let tag = git::run(repo_dir, &describe_args).ok();
+    let range = match &tag {
+        Some(tag) => format!("{}..HEAD", tag),
+        None => "HEAD".to_string(),
+    };
+    let log = git::run(
+        repo_dir,
+        &["log", "--no-merges", "--format=%H%x1f%B%x1e", &range],
+    )?;
+    let commits = log
+        .split('\x1e')
+        .filter_map(|entry| {
+            let (sha, message) = entry.trim().split_once('\x1f')?;
+
+            Some(Commit::parse(sha, message))
+        })
+        .collect();
+
+    Ok((tag, commits))
+}
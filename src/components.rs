@@ -0,0 +1,94 @@
+use crate::{ScriptError, json_node_extra::*, script_error, script_files::ScriptSources};
+use json5_nodes::JsonNode;
+
+/// The sections that a component can contain.
+const COMPONENT_SECTIONS: [&str; 3] = ["vars", "operations", "targets"];
+
+/// Get the names of the `components` of a script, in script order, or none if the script has no components.
+pub(crate) fn names(root_node: &JsonNode) -> Result<Vec<String>, ScriptError> {
+    let Ok(components_node) = root_node.get_object_entry("components") else {
+        return Ok(vec![]);
+    };
+    let names: Vec<String> = components_node
+        .get_object_iter()?
+        .map(|(name, _)| name.clone())
+        .collect();
+
+    if names.is_empty() {
+        return Err(script_error!(
+            "'components' must not be empty",
+            components_node
+        ));
+    }
+
+    Ok(names)
+}
+
+/// Check that a script with `components` has no shared `targets`.
+pub(crate) fn validate_targets(root_node: &JsonNode) -> Result<(), ScriptError> {
+    if root_node.get_object_entry("components").is_ok()
+        && let Ok(targets_node) = root_node.get_object_entry("targets")
+    {
+        return Err(script_error!(
+            "'targets' must be in each component when the script has 'components'",
+            targets_node
+        ));
+    }
+
+    Ok(())
+}
+
+/// Get the root node for running a component, which has the shared sections of the script with the
+/// `vars`, `operations` and `targets` of the component merged into them.
+///
+/// A component var or operation replaces a shared one with the same name. The nodes keep their locations,
+/// so each var is written back to the component it belongs to. Returns the root node with its sources.
+pub(crate) fn root_node(
+    root_node: &JsonNode,
+    sources: &ScriptSources,
+    name: &str,
+) -> Result<(JsonNode, ScriptSources), ScriptError> {
+    let components_node = root_node.get_object_entry("components")?;
+    let component_names = names(root_node)?;
+    let component_node = components_node.get_object_entry(name).map_err(|_| {
+        script_error!(
+            format!(
+                "Component '{}' not found, expected one of {}",
+                name,
+                component_names
+                    .iter()
+                    .map(|name| format!("'{}'", name))
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            ),
+            components_node
+        )
+    })?;
+    let JsonNode::Object(component_entries, _) = component_node.clone() else {
+        return Err(script_error!(
+            format!("Component '{}' must be an object", name),
+            component_node
+        ));
+    };
+
+    for (key, node) in component_entries.iter() {
+        if !COMPONENT_SECTIONS.contains(&key.as_str()) {
+            return Err(script_error!(
+                format!(
+                    "Component '{}' can only contain 'vars', 'operations' and 'targets', not '{}'",
+                    name, key
+                ),
+                node
+            ));
+        }
+    }
+
+    let JsonNode::Object(mut entries, location) = root_node.clone() else {
+        return Err(script_error!("Node <root> is not an object", root_node));
+    };
+
+    entries.remove("components");
+    let sources = sources.with_component(&mut entries, component_entries)?;
+
+    Ok((JsonNode::Object(entries, location), sources))
+}
@@ -0,0 +1,79 @@
+use evalexpr::{
+    ContextWithMutableFunctions, ContextWithMutableVariables, EvalexprError, EvalexprResult,
+    Function, HashMapContext, Value,
+};
+use jiff::{Zoned, civil::Date, fmt::strtime};
+
+/// Add the `now::` values and functions, and the `date::` functions, to an expression context.
+pub(crate) fn add_functions(context: &mut HashMapContext, now: &Zoned) -> EvalexprResult<()> {
+    for (identifier, value) in [
+        ("now::year", i64::from(now.year())),
+        ("now::month", i64::from(now.month())),
+        ("now::day", i64::from(now.day())),
+        ("now::hour", i64::from(now.hour())),
+        ("now::minute", i64::from(now.minute())),
+        ("now::second", i64::from(now.second())),
+        ("now::week", i64::from(now.date().iso_week_date().week())),
+        ("now::dayOfYear", i64::from(now.day_of_year())),
+        ("now::timestamp", now.timestamp().as_second()),
+    ] {
+        context.set_value(identifier.to_owned(), Value::Int(value))?;
+    }
+
+    let format_now = now.clone();
+
+    context.set_function(
+        "now::format".to_owned(),
+        Function::new(move |arg| {
+            let format = arg.as_string()?;
+
+            strtime::format(&format, &format_now)
+                .map(Value::from)
+                .map_err(|e| {
+                    EvalexprError::CustomMessage(format!(
+                        "Unable to format the date with '{}' - {}",
+                        format, e
+                    ))
+                })
+        }),
+    )?;
+
+    let today = now.date();
+
+    context.set_function(
+        "date::daysSince".to_owned(),
+        Function::new(move |arg| days_between(parse_date(&arg.as_string()?)?, today)),
+    )?;
+    context.set_function(
+        "date::daysBetween".to_owned(),
+        Function::new(|arg| {
+            let [start, end] = arg.as_fixed_len_tuple(2)?.try_into().unwrap();
+
+            days_between(
+                parse_date(&start.as_string()?)?,
+                parse_date(&end.as_string()?)?,
+            )
+        }),
+    )?;
+
+    Ok(())
+}
+
+/// Parse an ISO 8601 date such as `2020-01-01`.
+fn parse_date(s: &str) -> EvalexprResult<Date> {
+    s.parse().map_err(|e| {
+        EvalexprError::CustomMessage(format!(
+            "'{}' is not a valid date in the form YYYY-MM-DD - {}",
+            s, e
+        ))
+    })
+}
+
+/// The number of days from the start date to the end date, which is negative if the end is earlier.
+fn days_between(start: Date, end: Date) -> EvalexprResult<Value> {
+    let span = start
+        .until(end)
+        .map_err(|e| EvalexprError::CustomMessage(e.to_string()))?;
+
+    Ok(Value::Int(i64::from(span.get_days())))
+}
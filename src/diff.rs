@@ -0,0 +1,32 @@
+use similar::TextDiff;
+
+/// Create a unified diff between the old and new content of a file, suitable for `git apply`.
+///
+/// Returns `None` if the content is unchanged. A missing old file is diffed against `/dev/null`.
+pub(crate) fn unified_diff(label: &str, old: Option<&[u8]>, new: &[u8]) -> Option<String> {
+    if old == Some(new) {
+        return None;
+    }
+
+    let old_label = match old {
+        Some(_) => format!("a/{}", label),
+        None => "/dev/null".to_string(),
+    };
+    let new_label = format!("b/{}", label);
+    let (Ok(old_text), Ok(new_text)) = (
+        std::str::from_utf8(old.unwrap_or_default()),
+        std::str::from_utf8(new),
+    ) else {
+        return Some(format!(
+            "Binary files {} and {} differ\n",
+            old_label, new_label
+        ));
+    };
+
+    Some(
+        TextDiff::from_lines(old_text, new_text)
+            .unified_diff()
+            .header(&old_label, &new_label)
+            .to_string(),
+    )
+}
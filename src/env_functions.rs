@@ -0,0 +1,84 @@
+use evalexpr::{
+    ContextWithMutableFunctions, EvalexprError, EvalexprResult, Function, HashMapContext, Value,
+};
+use std::{env, sync::Arc};
+
+/// Add the `env::` functions to an expression context.
+///
+/// Only the environment variables named in `allowed_names`, from the `envVars` entry of the script, can be
+/// read, so that the environment a run depends on is explicit.
+pub(crate) fn add_functions(
+    context: &mut HashMapContext,
+    allowed_names: Vec<String>,
+) -> EvalexprResult<()> {
+    let allowed_names = Arc::new(allowed_names);
+    let get_names = allowed_names.clone();
+
+    context.set_function(
+        "env::get".to_owned(),
+        Function::new(move |arg| {
+            let name = arg.as_string()?;
+
+            match get_var(&get_names, &name)? {
+                Some(value) => Ok(Value::from(value)),
+                None => Err(EvalexprError::CustomMessage(format!(
+                    "Environment variable '{}' is not set",
+                    name
+                ))),
+            }
+        }),
+    )?;
+    context.set_function(
+        "env::get_or".to_owned(),
+        Function::new(move |arg| {
+            let [name, default] = arg.as_fixed_len_tuple(2)?.try_into().unwrap();
+            let name = name.as_string()?;
+
+            match get_var(&allowed_names, &name)? {
+                Some(value) => convert_var(&name, value, &default),
+                None => Ok(default),
+            }
+        }),
+    )?;
+
+    Ok(())
+}
+
+/// Read an allowed environment variable, returning `None` if it is not set.
+fn get_var(allowed_names: &[String], name: &str) -> EvalexprResult<Option<String>> {
+    if !allowed_names
+        .iter()
+        .any(|allowed_name| allowed_name == name)
+    {
+        return Err(EvalexprError::CustomMessage(format!(
+            "Environment variable '{}' is not listed in 'envVars'",
+            name
+        )));
+    }
+
+    match env::var(name) {
+        Ok(value) => Ok(Some(value)),
+        Err(env::VarError::NotPresent) => Ok(None),
+        Err(env::VarError::NotUnicode(_)) => Err(EvalexprError::CustomMessage(format!(
+            "Environment variable '{}' is not valid Unicode",
+            name
+        ))),
+    }
+}
+
+/// Convert the value of an environment variable to the type of a default value.
+fn convert_var(name: &str, value: String, default: &Value) -> EvalexprResult<Value> {
+    let (converted, type_name) = match default {
+        Value::Int(_) => (value.trim().parse().ok().map(Value::Int), "an integer"),
+        Value::Float(_) => (value.trim().parse().ok().map(Value::Float), "a float"),
+        Value::Boolean(_) => (value.trim().parse().ok().map(Value::Boolean), "a boolean"),
+        _ => (Some(Value::from(value.as_str())), "a string"),
+    };
+
+    converted.ok_or_else(|| {
+        EvalexprError::CustomMessage(format!(
+            "Environment variable '{}' value '{}' is not {}",
+            name, value, type_name
+        ))
+    })
+}
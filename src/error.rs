@@ -50,17 +50,15 @@ impl From<EvalexprError> for ScriptError {
 
 impl Display for ScriptError {
     fn fmt(&self, formatter: &mut fmt::Formatter<'_>) -> fmt::Result {
-        if let Some(file) = &self.script_file {
-            if let Some(location) = self.location {
-                return write!(
-                    formatter,
-                    "{} ({}:{}): {}",
-                    file.to_str().unwrap_or("???"),
-                    location.line,
-                    location.column,
-                    self.message
-                );
-            }
+        if let (Some(file), Some(location)) = (&self.script_file, self.location) {
+            return write!(
+                formatter,
+                "{} ({}:{}): {}",
+                file.to_str().unwrap_or("???"),
+                location.line,
+                location.column,
+                self.message
+            );
         }
         write!(formatter, "{}", self.message)
     }
@@ -0,0 +1,47 @@
+use std::{
+    ffi::OsStr,
+    path::{Path, PathBuf},
+    process::Command,
+};
+
+/// Run a local git command in a directory, returning its trimmed output or an error message.
+pub(crate) fn run<S: AsRef<OsStr>>(dir: &Path, args: &[S]) -> Result<String, String> {
+    let output = Command::new("git")
+        .arg("-C")
+        .arg(dir)
+        .args(args)
+        .env("GIT_OPTIONAL_LOCKS", "0")
+        .output()
+        .map_err(|e| format!("Unable to run git - {}", e))?;
+
+    if output.status.success() {
+        return Ok(String::from_utf8_lossy(&output.stdout).trim().to_string());
+    }
+
+    let stderr = String::from_utf8_lossy(&output.stderr);
+
+    if stderr.contains("not a git repository") {
+        Err(format!(
+            "'{}' is not in a git repository",
+            display_dir(dir).display()
+        ))
+    } else {
+        Err(format!(
+            "'git {}' failed in '{}' - {}",
+            args.iter()
+                .map(|arg| arg.as_ref().to_string_lossy())
+                .collect::<Vec<_>>()
+                .join(" "),
+            display_dir(dir).display(),
+            stderr.trim()
+        ))
+    }
+}
+
+fn display_dir(dir: &Path) -> PathBuf {
+    if dir.as_os_str().is_empty() {
+        PathBuf::from(".")
+    } else {
+        dir.to_path_buf()
+    }
+}
@@ -0,0 +1,76 @@
+use crate::git;
+use evalexpr::{
+    ContextWithMutableFunctions, EvalexprError, EvalexprResult, Function, HashMapContext, Value,
+};
+use std::path::Path;
+
+/// A function that reads a value from the repository containing a directory.
+type GitFunction = fn(&Path) -> EvalexprResult<Value>;
+
+/// Add the `git::` functions to an expression context.
+///
+/// The functions read the local repository containing `repo_dir` when they are called, so scripts that do
+/// not use them work outside of a repository.
+pub(crate) fn add_functions(context: &mut HashMapContext, repo_dir: &Path) -> EvalexprResult<()> {
+    let functions: [(&str, GitFunction); 7] = [
+        ("git::sha", |dir| {
+            git(dir, &["rev-parse", "HEAD"]).map(Value::from)
+        }),
+        ("git::shortSha", |dir| {
+            git(dir, &["rev-parse", "--short", "HEAD"]).map(Value::from)
+        }),
+        ("git::branch", |dir| {
+            // A detached HEAD has no branch
+            match git(dir, &["symbolic-ref", "--quiet", "--short", "HEAD"]) {
+                Ok(branch) => Ok(Value::from(branch)),
+                Err(_) => git(dir, &["rev-parse", "HEAD"]).map(|_| Value::from("")),
+            }
+        }),
+        ("git::commitCount", |dir| {
+            let count = git(dir, &["rev-list", "--count", "HEAD"])?;
+
+            count.parse().map(Value::Int).map_err(|_| {
+                EvalexprError::CustomMessage(format!("Unexpected commit count '{}'", count))
+            })
+        }),
+        ("git::describe", |dir| {
+            git(dir, &["describe", "--tags", "--always"]).map(Value::from)
+        }),
+        ("git::lastTag", |dir| {
+            // There may be no tags yet
+            match git(dir, &["describe", "--tags", "--abbrev=0"]) {
+                Ok(tag) => Ok(Value::from(tag)),
+                Err(_) => git(dir, &["rev-parse", "HEAD"]).map(|_| Value::from("")),
+            }
+        }),
+        ("git::isDirty", |dir| {
+            git(dir, &["status", "--porcelain", "--untracked-files=no"])
+                .map(|status| Value::Boolean(!status.is_empty()))
+        }),
+    ];
+
+    for (identifier, function) in functions {
+        let repo_dir = repo_dir.to_path_buf();
+
+        context.set_function(
+            identifier.to_owned(),
+            Function::new(move |arg| {
+                if !matches!(arg, Value::Empty) {
+                    return Err(EvalexprError::CustomMessage(format!(
+                        "'{}' does not take any arguments",
+                        identifier
+                    )));
+                }
+
+                function(&repo_dir)
+            }),
+        )?;
+    }
+
+    Ok(())
+}
+
+/// Run a local git command in a directory, returning its trimmed output.
+fn git(dir: &Path, args: &[&str]) -> EvalexprResult<String> {
+    git::run(dir, args).map_err(EvalexprError::CustomMessage)
+}
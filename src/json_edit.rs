@@ -0,0 +1,115 @@
+use evalexpr::Value;
+use json5_nodes::Location;
+use std::ops::Range;
+
+/// Convert a one-based line and column location into a byte offset within the content.
+pub(crate) fn location_offset(content: &str, location: Location) -> Option<usize> {
+    let mut line_start = 0;
+
+    for _ in 1..location.line {
+        line_start += content[line_start..].find('\n')? + 1;
+    }
+
+    let column_offset = content[line_start..]
+        .char_indices()
+        .nth(location.column - 1)
+        .map(|(offset, _)| offset)?;
+
+    Some(line_start + column_offset)
+}
+
+/// Get the byte range of the scalar value (string, number, boolean or null) that starts at `start`.
+pub(crate) fn scalar_span(content: &str, start: usize) -> Option<Range<usize>> {
+    let rest = &content[start..];
+    let first_char = rest.chars().next()?;
+
+    if first_char == '"' || first_char == '\'' {
+        let mut escaped = false;
+
+        for (offset, c) in rest.char_indices().skip(1) {
+            if escaped {
+                escaped = false;
+            } else if c == '\\' {
+                escaped = true;
+            } else if c == first_char {
+                return Some(start..start + offset + c.len_utf8());
+            }
+        }
+
+        None
+    } else {
+        let len = rest
+            .find(|c: char| c.is_whitespace() || matches!(c, ',' | '}' | ']' | '/'))
+            .unwrap_or(rest.len());
+
+        if len == 0 {
+            None
+        } else {
+            Some(start..start + len)
+        }
+    }
+}
+
+/// Get the byte range of the scalar value at a node location.
+pub(crate) fn scalar_span_at(content: &str, location: Location) -> Option<Range<usize>> {
+    scalar_span(content, location_offset(content, location)?)
+}
+
+/// Format a value as JSON5 text, or `None` if it has no JSON5 scalar representation.
+///
+/// Strings containing double quotes are single quoted to keep them readable.
+pub(crate) fn format_value(value: &Value) -> Option<String> {
+    match value {
+        Value::String(s) if s.contains('"') && !s.contains('\'') => Some(format_string(s, '\'')),
+        value => format_json_value(value),
+    }
+}
+
+/// Format a value as JSON text, which is also valid JSON5, or `None` if it has no JSON scalar representation.
+pub(crate) fn format_json_value(value: &Value) -> Option<String> {
+    match value {
+        Value::String(s) => Some(format_string(s, '"')),
+        Value::Float(f) if f.is_finite() => Some(format!("{:?}", f)),
+        Value::Int(n) => Some(n.to_string()),
+        Value::Boolean(b) => Some(b.to_string()),
+        _ => None,
+    }
+}
+
+/// Format a string as a quoted string, escaping as necessary.
+fn format_string(s: &str, quote: char) -> String {
+    let mut result = String::with_capacity(s.len() + 2);
+
+    result.push(quote);
+
+    for c in s.chars() {
+        match c {
+            '\\' => result.push_str("\\\\"),
+            '\n' => result.push_str("\\n"),
+            '\r' => result.push_str("\\r"),
+            '\t' => result.push_str("\\t"),
+            c if c == quote => {
+                result.push('\\');
+                result.push(c);
+            }
+            c if c.is_control() => result.push_str(&format!("\\u{:04x}", c as u32)),
+            c => result.push(c),
+        }
+    }
+
+    result.push(quote);
+    result
+}
+
+/// Replace non-overlapping byte ranges of the content with new text.
+pub(crate) fn apply_edits(content: &str, mut edits: Vec<(Range<usize>, String)>) -> String {
+    let mut new_content = content.to_string();
+
+    edits.sort_by_key(|(range, _)| std::cmp::Reverse(range.start));
+
+    for (range, text) in edits {
+        new_content.replace_range(range, &text);
+    }
+
+    new_content
+}
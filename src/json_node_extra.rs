@@ -4,7 +4,6 @@ use evalexpr::Value;
 use json5_nodes::{Iter, JsonNode, Location};
 
 #[allow(dead_code)]
-
 pub(crate) trait JsonNodeExtra {
     fn is_null(&self) -> bool;
     fn is_bool(&self) -> bool;
@@ -24,65 +23,37 @@ pub(crate) trait JsonNodeExtra {
 impl JsonNodeExtra for JsonNode {
     /// Is the node null?
     fn is_null(&self) -> bool {
-        if let JsonNode::Null(_) = self {
-            true
-        } else {
-            false
-        }
+        matches!(self, JsonNode::Null(_))
     }
 
     /// Is the node a boolean?
     fn is_bool(&self) -> bool {
-        if let JsonNode::Bool(_, _) = self {
-            true
-        } else {
-            false
-        }
+        matches!(self, JsonNode::Bool(_, _))
     }
 
     /// Is the node an integer?
     fn is_integer(&self) -> bool {
-        if let JsonNode::Integer(_, _) = self {
-            true
-        } else {
-            false
-        }
+        matches!(self, JsonNode::Integer(_, _))
     }
 
     /// Is the node a float?
     fn is_float(&self) -> bool {
-        if let JsonNode::Float(_, _) = self {
-            true
-        } else {
-            false
-        }
+        matches!(self, JsonNode::Float(_, _))
     }
 
     /// Is the node a string?
     fn is_string(&self) -> bool {
-        if let JsonNode::String(_, _) = self {
-            true
-        } else {
-            false
-        }
+        matches!(self, JsonNode::String(_, _))
     }
 
     /// Is the node an array?
     fn is_array(&self) -> bool {
-        if let JsonNode::Array(_, _) = self {
-            true
-        } else {
-            false
-        }
+        matches!(self, JsonNode::Array(_, _))
     }
 
     /// Is the node an object?
     fn is_object(&self) -> bool {
-        if let JsonNode::Object(_, _) = self {
-            true
-        } else {
-            false
-        }
+        matches!(self, JsonNode::Object(_, _))
     }
 
     /// Get the node location
@@ -99,7 +70,7 @@ impl JsonNodeExtra for JsonNode {
     }
 
     /// Get an object node entry
-    fn get_object_entry<'a>(self: &'a Self, name: &str) -> Result<&'a JsonNode, ScriptError> {
+    fn get_object_entry(&self, name: &str) -> Result<&JsonNode, ScriptError> {
         if let JsonNode::Object(map, ..) = self {
             if let Some(node) = map.get(name) {
                 Ok(node)
@@ -132,18 +103,18 @@ impl JsonNodeExtra for JsonNode {
         }
     }
 
-    fn get_value(self: &Self) -> Value {
+    fn get_value(&self) -> Value {
         match self {
             JsonNode::Null(..) => Value::Empty,
-            JsonNode::Integer(value, ..) => Value::Int(*value as i64),
-            JsonNode::Float(value, ..) => Value::Float(*value as f64),
+            JsonNode::Integer(value, ..) => Value::Int(*value),
+            JsonNode::Float(value, ..) => Value::Float(*value),
             JsonNode::Bool(value, ..) => Value::from(*value),
             JsonNode::String(value, ..) => Value::from((*value).to_owned()),
             _ => Value::Empty,
         }
     }
 
-    fn get_string(self: &Self) -> String {
+    fn get_string(&self) -> String {
         match self {
             JsonNode::Null(..) => "null".to_string(),
             JsonNode::Integer(value, ..) => (*value).to_string(),
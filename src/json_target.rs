@@ -0,0 +1,117 @@
+use crate::{
+    ScriptError, UpdateReport, json_edit,
+    json_node_extra::*,
+    key_path::{self, PathSegment},
+    script_error,
+};
+use evalexpr::HashMapContext;
+use json5_nodes::JsonNode;
+use std::path::Path;
+
+/// Apply the `json` entries of a target to the content of a JSON or JSON5 file.
+///
+/// Each entry replaces the value at a JSON Pointer or dotted key path with the result of an expression.
+/// Only the text of the value is changed, so key order, indentation and comments are preserved.
+pub(crate) fn update_json(
+    target_file: &Path,
+    content: &str,
+    json_node: &JsonNode,
+    context: &HashMapContext,
+    update_reports: &mut Vec<UpdateReport>,
+) -> Result<String, ScriptError> {
+    let root_node = json5_nodes::parse(content).map_err(|e| {
+        script_error!(
+            format!("File '{}' is not valid JSON - {}", target_file.display(), e),
+            json_node
+        )
+    })?;
+    let mut edits = vec![];
+
+    for entry_node in json_node.get_array_iter()? {
+        let path_node = entry_node.get_object_entry("path")?;
+        let path = path_node.get_string();
+        let segments =
+            key_path::parse(&path).map_err(|message| script_error!(message, path_node))?;
+        let value_node = entry_node.get_object_entry("value")?;
+        let value = evalexpr::eval_with_context(&value_node.get_string(), context)
+            .map_err(|e| script_error!(e.to_string(), value_node))?;
+        let text = json_edit::format_json_value(&value).ok_or_else(|| {
+            script_error!(
+                "'value' must evaluate to a string, integer, float or boolean",
+                value_node
+            )
+        })?;
+        let node = node_at_path(&root_node, &segments).map_err(|parent_node| {
+            let location = parent_node
+                .get_location()
+                .map(|l| format!(" at {}:{}", l.line, l.column))
+                .unwrap_or_default();
+
+            script_error!(
+                format!(
+                    "Key path '{}' not found in '{}'{}",
+                    path,
+                    target_file.display(),
+                    location
+                ),
+                path_node
+            )
+        })?;
+
+        if node.is_object() || node.is_array() {
+            return Err(script_error!(
+                format!(
+                    "Key path '{}' in '{}' is not a single value",
+                    path,
+                    target_file.display()
+                ),
+                path_node
+            ));
+        }
+
+        let span = node
+            .get_location()
+            .and_then(|location| json_edit::scalar_span_at(content, location))
+            .ok_or_else(|| {
+                script_error!(
+                    format!(
+                        "Unable to locate key path '{}' in '{}'",
+                        path,
+                        target_file.display()
+                    ),
+                    path_node
+                )
+            })?;
+
+        edits.retain(|(existing_span, _)| *existing_span != span);
+        edits.push((span, text));
+        update_reports.push(UpdateReport {
+            search: path,
+            match_count: 1,
+            skip_reason: None,
+        });
+    }
+
+    Ok(json_edit::apply_edits(content, edits))
+}
+
+/// Find the node at a key path, or return the deepest node found if the path does not exist.
+fn node_at_path<'a>(
+    node: &'a JsonNode,
+    segments: &[PathSegment],
+) -> Result<&'a JsonNode, &'a JsonNode> {
+    let Some((segment, rest)) = segments.split_first() else {
+        return Ok(node);
+    };
+    let child_node = match (node, segment) {
+        (JsonNode::Object(map, _), PathSegment::Key(key)) => map.get(key),
+        (JsonNode::Object(map, _), PathSegment::Index(index)) => map.get(&index.to_string()),
+        (JsonNode::Array(array, _), PathSegment::Index(index)) => array.get(*index),
+        _ => None,
+    };
+
+    match child_node {
+        Some(child_node) => node_at_path(child_node, rest),
+        None => Err(node),
+    }
+}
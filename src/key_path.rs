@@ -0,0 +1,95 @@
+#[derive(Clone, Debug, PartialEq)]
+/// A single segment of a key path.
+pub(crate) enum PathSegment {
+    /// An object, table or mapping key.
+    Key(String),
+    /// An array index.
+    Index(usize),
+}
+
+/// Parse a dotted key path such as `package.version`, `bin.0.name` or `dependencies."my.crate".version`.
+///
+/// Segments consisting only of digits are array indices. Segments can be double quoted to include dots
+/// or to force a numeric key.
+pub(crate) fn parse_dotted(path: &str) -> Result<Vec<PathSegment>, String> {
+    let mut segments = vec![];
+    let mut chars = path.chars().peekable();
+
+    loop {
+        let segment = if chars.peek() == Some(&'"') {
+            chars.next();
+
+            let mut key = String::new();
+
+            loop {
+                match chars.next() {
+                    Some('"') => break,
+                    Some(c) => key.push(c),
+                    None => return Err(format!("Key path '{}' has an unterminated quote", path)),
+                }
+            }
+
+            PathSegment::Key(key)
+        } else {
+            let mut key = String::new();
+
+            while let Some(c) = chars.next_if(|c| *c != '.') {
+                key.push(c);
+            }
+
+            if key.is_empty() {
+                return Err(format!("Key path '{}' has an empty segment", path));
+            }
+
+            match key.parse::<usize>() {
+                Ok(index) if key.bytes().all(|b| b.is_ascii_digit()) => PathSegment::Index(index),
+                _ => PathSegment::Key(key),
+            }
+        };
+
+        segments.push(segment);
+
+        match chars.next() {
+            Some('.') => continue,
+            Some(c) => {
+                return Err(format!(
+                    "Key path '{}' has an unexpected '{}' after a quoted segment",
+                    path, c
+                ));
+            }
+            None => break,
+        }
+    }
+
+    Ok(segments)
+}
+
+/// Parse a JSON Pointer such as `/expo/android/versionCode`, as described in RFC 6901.
+///
+/// Segments consisting only of digits are array indices, but also match object keys with the same name.
+pub(crate) fn parse_pointer(path: &str) -> Result<Vec<PathSegment>, String> {
+    let Some(rest) = path.strip_prefix('/') else {
+        return Err(format!("JSON Pointer '{}' must start with '/'", path));
+    };
+
+    Ok(rest
+        .split('/')
+        .map(|segment| {
+            let key = segment.replace("~1", "/").replace("~0", "~");
+
+            match key.parse::<usize>() {
+                Ok(index) if key.bytes().all(|b| b.is_ascii_digit()) => PathSegment::Index(index),
+                _ => PathSegment::Key(key),
+            }
+        })
+        .collect())
+}
+
+/// Parse either a JSON Pointer, if the path starts with `/`, or a dotted key path.
+pub(crate) fn parse(path: &str) -> Result<Vec<PathSegment>, String> {
+    if path.starts_with('/') {
+        parse_pointer(path)
+    } else {
+        parse_dotted(path)
+    }
+}
@@ -6,53 +6,267 @@
 //!
 #![deny(unsafe_code, missing_docs)]
 
+mod auto;
+mod changelog_target;
+mod commits;
+mod components;
+mod date_functions;
+mod diff;
+mod env_functions;
 mod error;
+mod git;
+mod git_functions;
+mod json_edit;
 mod json_node_extra;
+mod json_target;
+mod key_path;
+mod operation_calls;
+mod operation_params;
+mod prerelease;
+mod release;
+mod report;
+mod script_files;
+mod semver_functions;
+mod target_files;
+mod toml_target;
+mod transaction;
+mod var_overrides;
+mod xml_target;
+mod yaml_target;
 
 pub use error::ScriptError;
+pub use release::Release;
+pub use report::{FileReport, RunReport, TargetAction, TargetReport, UpdateReport};
+pub use transaction::Transaction;
+
+/// The pseudo-operation that checks every target file is in sync instead of running an operation.
+pub const CHECK_OPERATION: &str = "check";
+
+/// The pseudo-operation that chooses the operation to run from the Conventional Commits since the last tag.
+pub const AUTO_OPERATION: &str = auto::OPERATION;
 
 use anyhow::Context as AnyhowContext;
 use evalexpr::*;
-use jiff::{Zoned, tz::TimeZone};
+use jiff::{Timestamp, Zoned, tz::TimeZone};
 use json_node_extra::*;
 use json5_nodes::JsonNode;
 use regex::{Captures, RegexBuilder};
+use script_files::{ScriptPart, ScriptSources};
 use std::{
-    borrow::Cow,
-    fs,
+    ops::Range,
     path::{Path, PathBuf},
+    sync::{Arc, Mutex, PoisonError},
 };
 
+/// A function that applies a structured file action's entries to the content of a file.
+type StructuredUpdateFn = fn(
+    &Path,
+    &str,
+    &JsonNode,
+    &HashMapContext,
+    &mut Vec<UpdateReport>,
+) -> Result<String, ScriptError>;
+
+/// A script file path and content, with the edits to make to the content.
+type ScriptFileEdits = (PathBuf, String, Vec<(Range<usize>, String)>);
+
+/// The var holding the name of the last operation that ran, or `""` if none has.
+const OPERATION_NAME_VAR: &str = "op::name";
+
+/// The var holding a tuple of the names of the operations that ran, in order.
+const OPERATION_NAMES_VAR: &str = "op::names";
+
+/// Target actions that set values by key path in structured files, with the script entry name for each.
+const STRUCTURED_ACTIONS: [(&str, TargetAction, StructuredUpdateFn); 4] = [
+    ("toml", TargetAction::Toml, toml_target::update_toml),
+    ("json", TargetAction::Json, json_target::update_json),
+    ("yaml", TargetAction::Yaml, yaml_target::update_yaml),
+    ("xml", TargetAction::Xml, xml_target::update_xml),
+];
+
 /// Versioning tool for stamping version information into files.
-pub struct StampVerTool {}
+#[derive(Default)]
+pub struct StampVerTool {
+    now: Option<Timestamp>,
+    time_zone: Option<TimeZone>,
+    var_overrides: Vec<(String, var_overrides::Override)>,
+    persist_var_overrides: bool,
+    repo_dir: Option<PathBuf>,
+    /// The root nodes that the tool has read or made, newest last, with the file that each of their parts
+    /// came from.
+    roots: Mutex<Vec<(JsonNode, Arc<ScriptSources>)>>,
+}
 
 impl StampVerTool {
     /// Create a new instance of StampVerTool.
     pub fn new() -> StampVerTool {
-        StampVerTool {}
+        StampVerTool::default()
+    }
+
+    /// Use a fixed time for the `now::` values instead of the system clock, e.g. for reproducible builds.
+    pub fn with_now(mut self, now: Timestamp) -> StampVerTool {
+        self.now = Some(now);
+        self
+    }
+
+    /// Use a time zone for the `now::` values instead of the `tz` var. The `tz` var is not saved.
+    pub fn with_time_zone(mut self, time_zone: TimeZone) -> StampVerTool {
+        self.time_zone = Some(time_zone);
+        self
+    }
+
+    /// Read the `git::` values, the commits for `auto` and changelog targets, and commit releases in the
+    /// repository containing a directory, instead of the repository containing the script file.
+    pub fn with_repo_dir(mut self, repo_dir: impl Into<PathBuf>) -> StampVerTool {
+        self.repo_dir = Some(repo_dir.into());
+        self
+    }
+
+    /// Override the value of a `vars` or `calcVars` entry before the operation runs. Overridden vars are not
+    /// saved unless [`StampVerTool::with_persisted_var_overrides`] is used.
+    ///
+    /// A root node that does not define the var ignores the override, so it can apply to only the components
+    /// that define it. Use [`StampVerTool::check_var_overrides`] to check that each override names a var.
+    pub fn with_var_override(mut self, name: impl Into<String>, value: Value) -> StampVerTool {
+        self.var_overrides
+            .push((name.into(), var_overrides::Override::Value(value)));
+        self
+    }
+
+    /// Override the value of a `vars` or `calcVars` entry with text, which is converted for each root node
+    /// as by [`StampVerTool::parse_var_override`], so that it has the type of the var in each component.
+    pub fn with_var_override_text(
+        mut self,
+        name: impl Into<String>,
+        text: impl Into<String>,
+    ) -> StampVerTool {
+        self.var_overrides
+            .push((name.into(), var_overrides::Override::Text(text.into())));
+        self
+    }
+
+    /// Save overridden vars to the script file like any other changed var.
+    pub fn with_persisted_var_overrides(mut self) -> StampVerTool {
+        self.persist_var_overrides = true;
+        self
+    }
+
+    /// Parse the text of a var override, converting it to the type of the `vars` entry it overrides, or
+    /// inferring its type for a `calcVars` entry.
+    pub fn parse_var_override(
+        &self,
+        root_node: &JsonNode,
+        name: &str,
+        text: &str,
+    ) -> Result<Value, ScriptError> {
+        var_overrides::parse(root_node, name, text)
+            .map_err(|e| self.locate(root_node, var_overrides::script_part(root_node, name), e))
+    }
+
+    /// Check that each var override names a `vars` or `calcVars` entry in at least one of the root nodes,
+    /// e.g. those of the components being run.
+    pub fn check_var_overrides(&self, root_nodes: &[&JsonNode]) -> Result<(), ScriptError> {
+        var_overrides::check(root_nodes, &self.var_overrides).map_err(|e| {
+            match root_nodes.first() {
+                Some(root_node) => self.locate(root_node, ScriptPart::Section("calcVars"), e),
+                None => e,
+            }
+        })
     }
 
     /// Read the script file and return its content and root node.
+    ///
+    /// The `vars`, `calcVars`, `operations` and `targets` of any files that the script `extends` or
+    /// `include`s are merged into the root node. The tool records the file that each part of the root node
+    /// came from, so errors caused by nodes from those files have the file in [`ScriptError::script_file`].
     pub fn read_script_file(
-        self: &Self,
+        &self,
         input_file: PathBuf,
     ) -> anyhow::Result<(String, JsonNode, PathBuf)> {
-        let script_path = input_file.canonicalize()?;
-        let content = fs::read_to_string(&script_path)?;
-        let root_node = json5_nodes::parse(&content)?;
+        let (content, root_node, script_file, sources) = script_files::load(&input_file)?;
+
+        self.add_sources(&root_node, sources);
+
+        Ok((content, root_node, script_file))
+    }
 
-        Ok((content, root_node, script_path))
+    /// Record the sources of a root node that the tool returns.
+    fn add_sources(&self, root_node: &JsonNode, sources: ScriptSources) {
+        self.roots
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .push((root_node.clone(), Arc::new(sources)));
+    }
+
+    /// Get the sources of a root node that the tool returned, or no sources for any other root node.
+    fn sources(&self, root_node: &JsonNode) -> Arc<ScriptSources> {
+        self.roots
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .iter()
+            .rev()
+            .find(|(node, _)| script_files::is_same_node(node, root_node))
+            .map(|(_, sources)| sources.clone())
+            .unwrap_or_default()
+    }
+
+    /// Get the directory whose repository git is run in, which is the directory given to
+    /// [`StampVerTool::with_repo_dir`], otherwise the directory of the script file.
+    fn repo_dir(&self, script_file: Option<&Path>) -> PathBuf {
+        match (&self.repo_dir, script_file.and_then(Path::parent)) {
+            (Some(repo_dir), _) => repo_dir.clone(),
+            (None, Some(script_dir)) => script_dir.to_path_buf(),
+            (None, None) => PathBuf::from("."),
+        }
+    }
+
+    /// Set the file of an error caused by a part of a root node to the file that the part came from, if the
+    /// error does not already have one.
+    fn locate(&self, root_node: &JsonNode, part: ScriptPart, error: ScriptError) -> ScriptError {
+        self.sources(root_node).locate(part, error)
+    }
+
+    /// Get the names of the `components` in the script file, or none if it has no components.
+    pub fn component_names(&self, root_node: &JsonNode) -> Result<Vec<String>, ScriptError> {
+        let names = components::names(root_node)
+            .map_err(|e| self.locate(root_node, ScriptPart::Section("components"), e))?;
+
+        components::validate_targets(root_node)
+            .map_err(|e| self.locate(root_node, ScriptPart::Section("targets"), e))?;
+
+        Ok(names)
+    }
+
+    /// Get the root node for running a component, with the component's `vars`, `operations` and `targets`
+    /// merged into the shared sections of the script file.
+    pub fn component_root_node(
+        &self,
+        root_node: &JsonNode,
+        name: &str,
+    ) -> Result<JsonNode, ScriptError> {
+        let sources = self.sources(root_node);
+
+        components::validate_targets(root_node)
+            .map_err(|e| sources.locate(ScriptPart::Section("targets"), e))?;
+
+        let (component_root_node, component_sources) =
+            components::root_node(root_node, &sources, name)
+                .map_err(|e| sources.locate(ScriptPart::Section("components"), e))?;
+
+        self.add_sources(&component_root_node, component_sources);
+
+        Ok(component_root_node)
     }
 
     /// Validate the filter path.
     pub fn validate_filter_paths(
-        self: &Self,
-        filter_paths: &Vec<PathBuf>,
+        &self,
+        filter_paths: &[PathBuf],
         script_file: &Path,
     ) -> anyhow::Result<Vec<PathBuf>> {
         let mut cleaned_filter_paths = vec![];
 
-        if filter_paths.len() == 0 {
+        if filter_paths.is_empty() {
             cleaned_filter_paths.push(
                 script_file
                     .parent()
@@ -87,175 +301,331 @@ impl StampVerTool {
     }
 
     /// Validate the script file's root node.
-    pub fn validate_script_file(self: &Self, root_node: &JsonNode) -> Result<(), ScriptError> {
+    pub fn validate_script_file(&self, root_node: &JsonNode) -> Result<(), ScriptError> {
+        let sources = self.sources(root_node);
+
+        self.validate_sections(root_node, &sources)
+            .map_err(|e| sources.locate(ScriptPart::Root, e))
+    }
+
+    /// Validate the sections of the script file's root node, giving each error the file of the part that
+    /// caused it.
+    fn validate_sections(
+        &self,
+        root_node: &JsonNode,
+        sources: &ScriptSources,
+    ) -> Result<(), ScriptError> {
+        let locate_section = |key| move |e| sources.locate(ScriptPart::Section(key), e);
+
         if !root_node.is_object() {
             return Err(script_error!("Node <root> is not an object", root_node));
         }
 
         // vars node
         let vars_node = root_node.get_object_entry("vars")?;
-        let vars_iter = vars_node.get_object_iter()?;
+        let vars_iter = vars_node
+            .get_object_iter()
+            .map_err(locate_section("vars"))?;
 
         for (key, var_node) in vars_iter {
-            if key == "tz" && !var_node.is_string() {
-                return Err(script_error!("'tz' node must be a string", var_node));
+            let error = if key == "tz" && !var_node.is_string() {
+                script_error!("'tz' node must be a string", var_node)
             } else if !(var_node.is_string()
                 || var_node.is_integer()
                 || var_node.is_float()
                 || var_node.is_bool())
             {
-                return Err(script_error!(
-                    format!("'vars' entry must be a string, integer, float or boolean",),
+                script_error!(
+                    "'vars' entry must be a string, integer, float or boolean",
                     var_node
-                ));
-            }
+                )
+            } else {
+                continue;
+            };
+
+            return Err(sources.locate(ScriptPart::Entry("vars", key), error));
         }
 
         if let Ok(calc_vars_node) = root_node.get_object_entry("calcVars") {
-            let calc_vars_iter = calc_vars_node.get_object_iter()?;
+            let calc_vars_iter = calc_vars_node
+                .get_object_iter()
+                .map_err(locate_section("calcVars"))?;
 
             for (key, node) in calc_vars_iter {
                 if !node.is_string() {
-                    return Err(script_error!(
-                        format!("'calcVars' entry '{}' must be a string", key),
-                        calc_vars_node
+                    return Err(sources.locate(
+                        ScriptPart::Entry("calcVars", key),
+                        script_error!(
+                            format!("'calcVars' entry '{}' must be a string", key),
+                            calc_vars_node
+                        ),
+                    ));
+                }
+            }
+        }
+
+        if let Ok(env_vars_node) = root_node.get_object_entry("envVars") {
+            for env_var_node in env_vars_node
+                .get_array_iter()
+                .map_err(locate_section("envVars"))?
+            {
+                if !env_var_node.is_string() || env_var_node.get_string().is_empty() {
+                    return Err(sources.locate(
+                        ScriptPart::Section("envVars"),
+                        script_error!("'envVars' entry must be a non-empty string", env_var_node),
                     ));
                 }
             }
         }
 
         let operations_node = root_node.get_object_entry("operations")?;
-        let operations_iter = operations_node.get_object_iter()?;
+        let operations_iter = operations_node
+            .get_object_iter()
+            .map_err(locate_section("operations"))?;
 
         for (key, operation_node) in operations_iter {
-            if !operation_node.is_string() {
-                return Err(script_error!(
-                    format!("Operation '{}' must be a string", key),
+            let result = if key == CHECK_OPERATION {
+                Err(script_error!(
+                    format!(
+                        "Operation '{}' is built in and cannot be defined in 'operations'",
+                        CHECK_OPERATION
+                    ),
                     operation_node
-                ));
-            }
+                ))
+            } else {
+                auto::validate_operation_name(root_node, key, operation_node)
+                    .and_then(|_| {
+                        prerelease::validate_operation_name(root_node, key, operation_node)
+                    })
+                    .and_then(|_| operation_params::validate(key, operation_node, root_node))
+                    .and_then(|_| operation_calls::validate(operations_node, key))
+            };
+
+            result.map_err(|e| sources.locate(ScriptPart::Entry("operations", key), e))?;
         }
 
-        let targets_node = root_node.get_object_entry("targets")?;
-        let targets_iter = targets_node.get_array_iter()?;
+        prerelease::validate(root_node).map_err(locate_section("prerelease"))?;
+        release::validate(root_node).map_err(locate_section("release"))?;
+        auto::validate(root_node).map_err(locate_section("auto"))?;
 
-        if let None = targets_iter.clone().nth(0) {
-            return Err(script_error!("'targets' must not be empty", targets_node));
+        let targets_node = root_node.get_object_entry("targets")?;
+        let targets_iter = targets_node
+            .get_array_iter()
+            .map_err(locate_section("targets"))?;
+
+        if targets_iter.clone().next().is_none() {
+            return Err(sources.locate(
+                ScriptPart::Section("targets"),
+                script_error!("'targets' must not be empty", targets_node),
+            ));
         }
 
         for (index, target_node) in targets_iter.enumerate() {
-            if !target_node.is_object() {
-                return Err(script_error!(
-                    format!("'targets' entry '{}' must be an object", index),
-                    target_node
-                ));
-            }
+            self.validate_target(index, target_node)
+                .map_err(|e| sources.locate(ScriptPart::Target(index), e))?;
+        }
 
-            let description_node = target_node.get_object_entry("description")?;
+        Ok(())
+    }
 
-            if !description_node.is_string() {
-                return Err(script_error!(
-                    "'description' entry must be a string",
-                    description_node
-                ));
-            }
+    /// Validate a `targets` entry.
+    fn validate_target(&self, index: usize, target_node: &JsonNode) -> Result<(), ScriptError> {
+        if !target_node.is_object() {
+            return Err(script_error!(
+                format!("'targets' entry '{}' must be an object", index),
+                target_node
+            ));
+        }
 
-            let files_node = target_node.get_object_entry("files")?;
-            let mut files_iter = files_node.get_array_iter()?;
+        let description_node = target_node.get_object_entry("description")?;
 
-            if let None = files_iter.nth(0) {
-                return Err(script_error!("'files' must not be empty", files_node));
-            }
+        if !description_node.is_string() {
+            return Err(script_error!(
+                "'description' entry must be a string",
+                description_node
+            ));
+        }
 
-            let updates_node = target_node.get_object_entry("updates").ok();
-            let write_node = target_node.get_object_entry("write").ok();
-            let copy_from_node = target_node.get_object_entry("copyFrom").ok();
+        let files_node = target_node.get_object_entry("files")?;
+        let files_iter = files_node.get_array_iter()?;
 
-            if let Some(updates_node) = updates_node {
-                let updates_iter = updates_node.get_array_iter()?;
+        if files_iter.clone().next().is_none() {
+            return Err(script_error!("'files' must not be empty", files_node));
+        }
 
-                if let None = updates_iter.clone().nth(0) {
-                    return Err(script_error!("'updates' must not be empty", updates_node));
-                }
+        for file_node in files_iter {
+            if !file_node.is_string() {
+                return Err(script_error!("'files' entry must be a string", file_node));
+            }
+        }
 
-                for (index, item_node) in updates_iter.enumerate() {
-                    if !item_node.is_object() {
-                        return Err(script_error!(
-                            format!("'updates' entry '{}' must be an object", index),
-                            target_node
-                        ));
-                    }
+        if let Ok(respect_gitignore_node) = target_node.get_object_entry("respectGitignore")
+            && !respect_gitignore_node.is_bool()
+        {
+            return Err(script_error!(
+                "'respectGitignore' entry must be a boolean",
+                respect_gitignore_node
+            ));
+        }
 
-                    let search_node = item_node.get_object_entry("search")?;
-                    let replace_node = item_node.get_object_entry("replace")?;
+        self.validate_when(target_node)?;
 
-                    if !search_node.is_string() {
-                        return Err(script_error!("'search' entry must be string", search_node));
-                    }
+        let updates_node = target_node.get_object_entry("updates").ok();
+        let structured_action = self.get_structured_action(target_node);
+        let write_node = target_node.get_object_entry("write").ok();
+        let changelog_node = target_node.get_object_entry("changelog").ok();
+        let copy_from_node = target_node.get_object_entry("copyFrom").ok();
 
-                    if !replace_node.is_string() {
-                        return Err(script_error!(
-                            "'replace' entry must be string",
-                            replace_node
-                        ));
-                    }
+        if let Some(updates_node) = updates_node {
+            let updates_iter = updates_node.get_array_iter()?;
+
+            if updates_iter.clone().next().is_none() {
+                return Err(script_error!("'updates' must not be empty", updates_node));
+            }
+
+            for (index, item_node) in updates_iter.enumerate() {
+                if !item_node.is_object() {
+                    return Err(script_error!(
+                        format!("'updates' entry '{}' must be an object", index),
+                        target_node
+                    ));
                 }
-            } else if let Some(write_node) = write_node {
-                if !write_node.is_string() {
-                    return Err(script_error!("'write' entry must be string", write_node));
+
+                let search_node = item_node.get_object_entry("search")?;
+                let replace_node = item_node.get_object_entry("replace")?;
+
+                if !search_node.is_string() {
+                    return Err(script_error!("'search' entry must be string", search_node));
                 }
-            } else if let Some(copy_from_node) = copy_from_node {
-                if !copy_from_node.is_string() {
+
+                if !replace_node.is_string() {
                     return Err(script_error!(
-                        "'copyFrom' entry must be string",
-                        copy_from_node
+                        "'replace' entry must be string",
+                        replace_node
                     ));
                 }
-            } else {
+
+                self.validate_when(item_node)?;
+            }
+        } else if let Some((name, _, _, entries_node)) = structured_action {
+            self.validate_path_updates(name, entries_node)?;
+        } else if let Some(write_node) = write_node {
+            if !write_node.is_string() {
+                return Err(script_error!("'write' entry must be string", write_node));
+            }
+        } else if let Some(changelog_node) = changelog_node {
+            changelog_target::validate(changelog_node)?;
+        } else if let Some(copy_from_node) = copy_from_node {
+            if !copy_from_node.is_string() {
                 return Err(script_error!(
-                    "Target must contain 'updates', 'write' or 'copyFrom'",
-                    target_node
+                    "'copyFrom' entry must be string",
+                    copy_from_node
                 ));
             }
+        } else {
+            return Err(script_error!(
+                "Target must contain 'updates', 'toml', 'json', 'yaml', 'xml', 'write', 'changelog' or 'copyFrom'",
+                target_node
+            ));
         }
 
         Ok(())
     }
 
-    /// Create a run context from the root node.
-    pub fn create_run_context(
-        self: &Self,
-        root_node: &JsonNode,
-    ) -> Result<HashMapContext, ScriptError> {
+    /// Validate the optional `when` entry of a target or `updates` entry.
+    fn validate_when(&self, node: &JsonNode) -> Result<(), ScriptError> {
+        if let Ok(when_node) = node.get_object_entry("when")
+            && !when_node.is_string()
+        {
+            return Err(script_error!("'when' entry must be string", when_node));
+        }
+
+        Ok(())
+    }
+
+    /// Get the name, action and entries node of a structured file action in a target, if it has one.
+    fn get_structured_action<'a>(
+        &self,
+        target_node: &'a JsonNode,
+    ) -> Option<(&'static str, TargetAction, StructuredUpdateFn, &'a JsonNode)> {
+        STRUCTURED_ACTIONS
+            .iter()
+            .find_map(|(name, action, update_fn)| {
+                target_node
+                    .get_object_entry(name)
+                    .ok()
+                    .map(|node| (*name, *action, *update_fn, node))
+            })
+    }
+
+    /// Validate an array of `{ path: , value: }` objects for a structured file action.
+    fn validate_path_updates(&self, name: &str, node: &JsonNode) -> Result<(), ScriptError> {
+        let entries_iter = node.get_array_iter()?;
+
+        if entries_iter.clone().next().is_none() {
+            return Err(script_error!(format!("'{}' must not be empty", name), node));
+        }
+
+        for (index, entry_node) in entries_iter.enumerate() {
+            if !entry_node.is_object() {
+                return Err(script_error!(
+                    format!("'{}' entry '{}' must be an object", name, index),
+                    entry_node
+                ));
+            }
+
+            let path_node = entry_node.get_object_entry("path")?;
+            let value_node = entry_node.get_object_entry("value")?;
+
+            if !path_node.is_string() {
+                return Err(script_error!("'path' entry must be string", path_node));
+            }
+
+            if !value_node.is_string() {
+                return Err(script_error!("'value' entry must be string", value_node));
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Create a run context from the root node of a script file.
+    pub fn create_run_context(&self, root_node: &JsonNode) -> Result<HashMapContext, ScriptError> {
         let mut context = HashMapContext::new();
 
         // Add all fixed vars into the context
         for (identifier, var_node) in root_node.get_object_entry("vars")?.get_object_iter()? {
-            context.set_value(identifier.to_string(), Value::from(var_node.get_value()))?;
+            context.set_value(identifier.to_string(), var_node.get_value())?;
+        }
+
+        for (name, var_override) in self.var_overrides.iter() {
+            var_overrides::apply(root_node, name, var_override, &mut context).map_err(|e| {
+                self.locate(root_node, var_overrides::script_part(root_node, name), e)
+            })?;
         }
 
         let tz: TimeZone;
 
-        if let Some(Value::String(tz_value)) = context.get_value("tz") {
+        if let Some(time_zone) = &self.time_zone {
+            tz = time_zone.clone();
+        } else if let Some(Value::String(tz_value)) = context.get_value("tz") {
             let iana_name = tz_value.as_str();
             tz = TimeZone::get(iana_name).map_err(|e| script_error!(e.to_string()))?;
         } else {
             tz = TimeZone::system();
             log::warn!(
                 "'tz' value not set or not a string; using system time zone '{}'",
-                tz.iana_name().unwrap()
+                tz.iana_name().unwrap_or_default()
             );
         }
 
-        context.set_value("tz".to_owned(), Value::from(tz.iana_name().unwrap()))?;
-
-        let now: Zoned = Zoned::now();
+        if let Some(iana_name) = tz.iana_name() {
+            context.set_value("tz".to_owned(), Value::from(iana_name))?;
+        }
 
-        now.with_time_zone(tz);
+        let now = Zoned::new(self.now.unwrap_or_else(Timestamp::now), tz);
 
-        context.set_value("now::year".to_owned(), Value::Int(i64::from(now.year())))?;
-        context.set_value("now::month".to_owned(), Value::Int(i64::from(now.month())))?;
-        context.set_value("now::day".to_owned(), Value::Int(i64::from(now.day())))?;
+        date_functions::add_functions(&mut context, &now)?;
         context.set_function(
             "if".to_owned(),
             Function::new(|arg| {
@@ -274,13 +644,41 @@ impl StampVerTool {
                 }
             }),
         )?;
+        semver_functions::add_functions(&mut context)?;
+        operation_calls::add_functions(&mut context)?;
+        context.set_value(OPERATION_NAME_VAR.to_owned(), Value::from(""))?;
+        context.set_value(OPERATION_NAMES_VAR.to_owned(), Value::Tuple(vec![]))?;
+        env_functions::add_functions(
+            &mut context,
+            match root_node.get_object_entry("envVars") {
+                Ok(env_vars_node) => env_vars_node
+                    .get_array_iter()?
+                    .map(|node| node.get_string())
+                    .collect(),
+                Err(_) => vec![],
+            },
+        )?;
+        git_functions::add_functions(
+            &mut context,
+            &self.repo_dir(self.sources(root_node).script_file()),
+        )?;
 
         // Evaluate the calculated vars
         for (identifier, calc_var_node) in
             root_node.get_object_entry("calcVars")?.get_object_iter()?
         {
+            if self.is_overridden(identifier) {
+                continue;
+            }
+
             let value = evalexpr::eval_with_context(&calc_var_node.get_string(), &context)
-                .map_err(|e| script_error!(e.to_string(), calc_var_node))?;
+                .map_err(|e| {
+                    self.locate(
+                        root_node,
+                        ScriptPart::Entry("calcVars", identifier),
+                        script_error!(e.to_string(), calc_var_node),
+                    )
+                })?;
 
             context.set_value(identifier.to_owned(), value)?;
         }
@@ -288,265 +686,775 @@ impl StampVerTool {
         Ok(context)
     }
 
+    /// Is a var overridden?
+    fn is_overridden(&self, identifier: &str) -> bool {
+        self.var_overrides
+            .iter()
+            .any(|(name, _)| name == identifier)
+    }
+
+    /// Evaluate every target without running an operation, to find files that are out of sync with the vars.
+    ///
+    /// Nothing is written. Use [`FileReport::out_of_sync_reason`] on the files in the report to find the files
+    /// that differ from what [`StampVerTool::process_targets`] would produce.
+    pub fn check_targets(
+        &self,
+        script_file: &Path,
+        root_node: &JsonNode,
+        context: &mut HashMapContext,
+        filter_paths: &[PathBuf],
+    ) -> Result<RunReport, ScriptError> {
+        self.stage_targets(
+            script_file,
+            root_node,
+            context,
+            filter_paths,
+            &mut Transaction::new(),
+        )
+    }
+
     /// Run an operation from the script file.
     pub fn run_operation(
-        self: &Self,
+        &self,
         operation: Option<String>,
         root_node: &JsonNode,
         context: &mut HashMapContext,
+    ) -> Result<(), ScriptError> {
+        self.run_operation_with_args(operation, &[], root_node, context)
+    }
+
+    /// Run an operation from the script file, or a built-in operation, with arguments.
+    ///
+    /// Operations with `params` take `name=value` arguments. Built-in operations, such as `nextPrerelease` when
+    /// the script has a `prerelease` section, take their own arguments.
+    pub fn run_operation_with_args(
+        &self,
+        operation: Option<String>,
+        args: &[String],
+        root_node: &JsonNode,
+        context: &mut HashMapContext,
     ) -> Result<(), ScriptError> {
         let operations_node = root_node.get_object_entry("operations")?;
 
         if let Some(operation) = operation {
-            let operation_node = operations_node.get_object_entry(&operation).map_err(|_| {
-                script_error!(
-                    format!("Operation '{}' not found", operation),
-                    operations_node
+            let mut operation_names = match context.get_value(OPERATION_NAMES_VAR) {
+                Some(Value::Tuple(operation_names)) => operation_names.clone(),
+                _ => vec![],
+            };
+            let position = operation_names.len() + 1;
+            let sources = self.sources(root_node);
+            let run_start_operation = |start_operation: &str, context: &mut HashMapContext| {
+                operation_params::run_operation(
+                    operations_node,
+                    start_operation,
+                    &[],
+                    context,
+                    &sources,
                 )
-            })?;
+                .map_err(|e| sources.locate(ScriptPart::Entry("operations", start_operation), e))
+            };
+
+            if prerelease::run_operation(
+                &operation,
+                position,
+                args,
+                root_node,
+                context,
+                run_start_operation,
+            )
+            .map_err(|e| self.locate(root_node, ScriptPart::Section("prerelease"), e))?
+            {
+                log::info!("Operation '{}'", operation);
+            } else if operations_node.get_object_entry(&operation).is_ok() {
+                let prerelease_vars = prerelease::vars(root_node, context);
+
+                operation_params::run_operation(
+                    operations_node,
+                    &operation,
+                    args,
+                    context,
+                    &sources,
+                )
+                .map_err(|e| sources.locate(ScriptPart::Entry("operations", &operation), e))?;
+                prerelease::reset_after_bump(&operation, prerelease_vars, root_node, context)
+                    .map_err(|e| self.locate(root_node, ScriptPart::Section("prerelease"), e))?;
+            } else {
+                return Err(self.locate(
+                    root_node,
+                    ScriptPart::Section("operations"),
+                    script_error!(
+                        format!("Operation '{}' not found", operation),
+                        operations_node
+                    ),
+                ));
+            }
+
+            operation_names.push(Value::from(operation.as_str()));
+            context.set_value(OPERATION_NAME_VAR.to_owned(), Value::from(operation))?;
+            context.set_value(
+                OPERATION_NAMES_VAR.to_owned(),
+                Value::Tuple(operation_names),
+            )?;
 
-            log::info!("Operation '{}'", operation);
-            evalexpr::eval_with_context_mut(&operation_node.get_string(), context)
-                .map_err(|e| script_error!(e.to_string(), operation_node))?;
             Ok(())
         } else {
-            Err(script_error!(
-                format!(
-                    "Specify a valid operation, one of {}",
+            Err(self.locate(
+                root_node,
+                ScriptPart::Section("operations"),
+                script_error!(
+                    format!(
+                        "Specify a valid operation, one of {}",
+                        self.operation_names(root_node)?
+                            .iter()
+                            .map(|name| format!("'{}'", name))
+                            .collect::<Vec<_>>()
+                            .join(", ")
+                    ),
                     operations_node
-                        .get_object_iter()?
-                        .map(|(identifier, _)| format!("'{}'", identifier))
-                        .collect::<Vec<_>>()
-                        .join(", ")
                 ),
-                operations_node
             ))
         }
     }
 
-    /// Process the targets defined in the script file.
+    /// Run several operations in order, each with its arguments.
+    pub fn run_operations(
+        &self,
+        operations: &[(String, Vec<String>)],
+        root_node: &JsonNode,
+        context: &mut HashMapContext,
+    ) -> Result<(), ScriptError> {
+        if operations.is_empty() {
+            return self.run_operation(None, root_node, context);
+        }
+
+        for (operation, args) in operations.iter() {
+            self.run_operation_with_args(Some(operation.clone()), args, root_node, context)?;
+        }
+
+        Ok(())
+    }
+
+    /// Split command line words into operations and their arguments.
+    ///
+    /// Each word that names an operation, including the built-in ones, starts a new operation, and the words
+    /// after it up to the next operation are its arguments.
+    pub fn parse_operations(
+        &self,
+        root_node: &JsonNode,
+        words: &[String],
+    ) -> Result<Vec<(String, Vec<String>)>, ScriptError> {
+        let operation_names = self.operation_names(root_node)?;
+        let mut operations: Vec<(String, Vec<String>)> = vec![];
+
+        for word in words.iter() {
+            if operation_names.contains(word) {
+                operations.push((word.clone(), vec![]));
+            } else if let Some((_, args)) = operations.last_mut() {
+                args.push(word.clone());
+            } else {
+                return Err(self.locate(
+                    root_node,
+                    ScriptPart::Section("operations"),
+                    script_error!(
+                        format!("Operation '{}' not found", word),
+                        root_node.get_object_entry("operations")?
+                    ),
+                ));
+            }
+        }
+
+        Ok(operations)
+    }
+
+    /// The names of the operations in the script file, followed by the built-in operations it enables.
+    fn operation_names(&self, root_node: &JsonNode) -> Result<Vec<String>, ScriptError> {
+        let mut operation_names = root_node
+            .get_object_entry("operations")?
+            .get_object_iter()?
+            .map(|(identifier, _)| identifier.to_string())
+            .collect::<Vec<_>>();
+
+        if root_node.get_object_entry("auto").is_ok() {
+            operation_names.push(auto::OPERATION.to_string());
+        }
+
+        if root_node.get_object_entry("prerelease").is_ok() {
+            operation_names.extend(prerelease::OPERATIONS.iter().map(|name| name.to_string()));
+        }
+
+        Ok(operation_names)
+    }
+
+    /// Choose the operation to run from the Conventional Commits since the last tag in the repository
+    /// containing the script file, using the operations named in the `auto` section.
+    pub fn choose_auto_operation(
+        &self,
+        script_file: &Path,
+        root_node: &JsonNode,
+    ) -> Result<String, ScriptError> {
+        auto::choose_operation(&self.repo_dir(Some(script_file)), root_node)
+            .map_err(|e| self.locate(root_node, ScriptPart::Section("auto"), e))
+    }
+
+    /// Evaluate the `release` section of the script file, if there is one, giving the tag and message.
+    pub fn evaluate_release(
+        &self,
+        root_node: &JsonNode,
+        context: &HashMapContext,
+    ) -> Result<Option<Release>, ScriptError> {
+        release::evaluate(root_node, context)
+            .map_err(|e| self.locate(root_node, ScriptPart::Section("release"), e))
+    }
+
+    /// Check that a release can be tagged in the repository containing the script file.
+    ///
+    /// Call this before committing the transaction, so that no files are changed if the tag already exists.
+    pub fn check_release_tag(&self, script_file: &Path, release: &Release) -> anyhow::Result<()> {
+        release::check_tag(&self.repo_dir(Some(script_file)), release).map_err(anyhow::Error::msg)
+    }
+
+    /// Commit exactly the given files in the repository containing the script file, and optionally tag it.
+    pub fn commit_release(
+        &self,
+        script_file: &Path,
+        release: &Release,
+        paths: &[PathBuf],
+        tag: bool,
+    ) -> anyhow::Result<()> {
+        release::commit(&self.repo_dir(Some(script_file)), release, paths, tag)
+            .map_err(anyhow::Error::msg)
+    }
+
+    /// Process the targets defined in the script file, returning a report of what was done.
+    ///
+    /// If `update` is true the changed files are written together, or not at all. Use
+    /// [`StampVerTool::stage_targets`] to write them in the same transaction as other changes.
     pub fn process_targets(
-        self: &Self,
-        script_file: &PathBuf,
+        &self,
+        script_file: &Path,
         root_node: &JsonNode,
         update: bool,
         context: &mut HashMapContext,
-        filter_paths: &Vec<PathBuf>,
-    ) -> Result<(), ScriptError> {
-        let version_file_dir = script_file.parent().unwrap_or(Path::new("."));
+        filter_paths: &[PathBuf],
+    ) -> Result<RunReport, ScriptError> {
+        let mut transaction = Transaction::new();
+        let mut run_report = self.stage_targets(
+            script_file,
+            root_node,
+            context,
+            filter_paths,
+            &mut transaction,
+        )?;
 
-        for target_node in root_node.get_object_entry("targets")?.get_array_iter()? {
-            for target_file_node in target_node.get_object_entry("files")?.get_array_iter()? {
-                let updates_node = target_node.get_object_entry("updates").ok();
-                let write_node = target_node.get_object_entry("write").ok();
-                let copy_from_node = target_node.get_object_entry("copyFrom").ok();
-                let mut action = "".to_string();
-                let mut target_file = PathBuf::from(target_file_node.get_string());
+        if update {
+            transaction.commit()?;
+            run_report.mark_applied();
+        }
 
-                if target_file.is_absolute() {
-                    return Err(script_error!(
-                        format!(
-                            "Target file '{}' is an absolute path, but should be relative to the script file",
-                            target_file.display().to_string()
-                        ),
-                        target_file_node
-                    ));
-                }
+        Ok(run_report)
+    }
 
-                target_file = path_clean::clean(version_file_dir.join(target_file));
+    /// Process the targets defined in the script file, returning a report of what would be done.
+    ///
+    /// New file content is staged in the transaction and is not written until it is committed, after which
+    /// [`RunReport::mark_applied`] records that the files were written.
+    pub fn stage_targets(
+        &self,
+        script_file: &Path,
+        root_node: &JsonNode,
+        context: &mut HashMapContext,
+        filter_paths: &[PathBuf],
+        transaction: &mut Transaction,
+    ) -> Result<RunReport, ScriptError> {
+        let sources = self.sources(root_node);
+        let base_dir = sources.base_dir(script_file);
+        let mut run_report = RunReport::default();
+        let targets_node = root_node
+            .get_object_entry("targets")
+            .map_err(|e| sources.locate(ScriptPart::Root, e))?;
+
+        for (index, target_node) in targets_node
+            .get_array_iter()
+            .map_err(|e| sources.locate(ScriptPart::Section("targets"), e))?
+            .enumerate()
+        {
+            let target_report = self
+                .stage_target(
+                    script_file,
+                    &base_dir,
+                    target_node,
+                    context,
+                    filter_paths,
+                    transaction,
+                )
+                .map_err(|e| sources.locate(ScriptPart::Target(index), e))?;
 
-                if !filter_paths
-                    .iter()
-                    .any(|path| target_file.starts_with(path))
-                {
-                    log::warn!(
-                        "File '{}' is outside the filter path and will be skipped",
-                        target_file.display().to_string()
-                    );
-                    continue;
-                }
+            run_report.targets.push(target_report);
+        }
+
+        Ok(run_report)
+    }
+
+    /// Process a single target, staging the new content of its files in the transaction.
+    fn stage_target(
+        &self,
+        script_file: &Path,
+        base_dir: &Path,
+        target_node: &JsonNode,
+        context: &mut HashMapContext,
+        filter_paths: &[PathBuf],
+        transaction: &mut Transaction,
+    ) -> Result<TargetReport, ScriptError> {
+        let version_file_dir = script_file.parent().unwrap_or(Path::new("."));
+        let mut target_report = TargetReport {
+            description: target_node.get_object_entry("description")?.get_string(),
+            files: vec![],
+            skipped_files: vec![],
+            skip_reason: self.when_skip_reason(target_node, context)?,
+        };
+
+        if let Some(skip_reason) = &target_report.skip_reason {
+            log::info!(
+                "Target '{}' is skipped because {}",
+                target_report.description,
+                skip_reason
+            );
+            return Ok(target_report);
+        }
+
+        let respect_gitignore = target_node
+            .get_object_entry("respectGitignore")
+            .is_ok_and(|node| node.get_value() == Value::Boolean(true));
+        let target_files = target_files::expand_target_files(
+            target_node.get_object_entry("files")?,
+            version_file_dir,
+            respect_gitignore,
+        )?;
+
+        for (target_file, target_file_node) in target_files {
+            let updates_node = target_node.get_object_entry("updates").ok();
+            let structured_action = self.get_structured_action(target_node);
+            let write_node = target_node.get_object_entry("write").ok();
+            let changelog_node = target_node.get_object_entry("changelog").ok();
+            let copy_from_node = target_node.get_object_entry("copyFrom").ok();
+
+            if !filter_paths
+                .iter()
+                .any(|path| target_file.starts_with(path))
+            {
+                log::warn!(
+                    "File '{}' is outside the filter path and will be skipped",
+                    target_file.display()
+                );
+                target_report.skipped_files.push(target_file);
+                continue;
+            }
 
-                if let Some(updates_node) = updates_node {
-                    let mut content = fs::read_to_string(&target_file).map_err(|_| {
+            let mut update_reports = vec![];
+            let (action, old_content, new_content) = if let Some(updates_node) = updates_node {
+                let content = self.read_target_file(transaction, &target_file, target_file_node)?;
+                let new_content = self.update_content(
+                    &target_file,
+                    &content,
+                    updates_node,
+                    context,
+                    &mut update_reports,
+                )?;
+
+                (
+                    TargetAction::Update,
+                    Some(content.into_bytes()),
+                    new_content.into_bytes(),
+                )
+            } else if let Some((_, action, update_fn, entries_node)) = structured_action {
+                let content = self.read_target_file(transaction, &target_file, target_file_node)?;
+                let new_content = update_fn(
+                    &target_file,
+                    &content,
+                    entries_node,
+                    context,
+                    &mut update_reports,
+                )?;
+
+                (action, Some(content.into_bytes()), new_content.into_bytes())
+            } else if let Some(changelog_node) = changelog_node {
+                let old_content = transaction.read(&target_file).ok();
+                let content = match &old_content {
+                    Some(bytes) => std::str::from_utf8(bytes).map_err(|_| {
                         script_error!(
-                            format!(
-                                "File '{}' does not exist or is not readable",
-                                target_file.display().to_string()
-                            ),
+                            format!("File '{}' is not valid UTF-8", target_file.display()),
                             target_file_node
                         )
-                    })?;
-
-                    for replacement_node in updates_node.get_array_iter()? {
-                        let search_node = replacement_node.get_object_entry("search")?;
-                        let search_str = search_node.get_string();
-                        let re = RegexBuilder::new(&search_str)
-                            .multi_line(true)
-                            .build()
-                            .map_err(|e| {
-                                script_error!(
-                                    format!("Regex is not valid - {}", e.to_string()),
-                                    search_node
-                                )
-                            })?;
-                        let replace_node = replacement_node.get_object_entry("replace")?;
-                        let replace_str = &replace_node.get_string();
-                        let mut found = false;
-                        let mut replace_err: Option<EvalexprError> = None;
-
-                        content = re
-                            .replace_all(&content, |caps: &Captures| {
-                                found = true;
-
-                                if let Some(m) = caps.name("begin") {
-                                    context
-                                        .set_value("begin".to_owned(), Value::from(m.as_str()))
-                                        .unwrap();
-                                }
-                                if let Some(m) = caps.name("end") {
-                                    context
-                                        .set_value("end".to_owned(), Value::from(m.as_str()))
-                                        .unwrap();
-                                }
-                                let result = eval_string_with_context(replace_str, context);
-
-                                match result {
-                                    Ok(s) => s,
-                                    Err(_) => {
-                                        replace_err = result.err();
-                                        String::new()
-                                    }
-                                }
-                            })
-                            .into_owned();
-
-                        if let Some(err) = replace_err {
-                            return Err(script_error!(err.to_string(), replace_node));
-                        }
+                    })?,
+                    None => "",
+                };
+                let new_content = changelog_target::update_changelog(
+                    &self.repo_dir(Some(script_file)),
+                    content,
+                    changelog_node,
+                    context,
+                )?;
+
+                (
+                    TargetAction::Changelog,
+                    old_content,
+                    new_content.into_bytes(),
+                )
+            } else if let Some(copy_from_node) = copy_from_node {
+                let copy_from_str = copy_from_node.get_string();
+                let s = eval_string_with_context(&copy_from_str, context)
+                    .map_err(|e| script_error!(e.to_string(), copy_from_node))?;
+                let from_file = version_file_dir.join(s);
+                let new_content = transaction.read(&from_file).map_err(|_| {
+                    script_error!(
+                        format!(
+                            "unable to copy {} to {}",
+                            from_file.display(),
+                            target_file.display(),
+                        ),
+                        copy_from_node
+                    )
+                })?;
 
-                        if !found {
-                            log::warn!(
-                                "Search/replace in '{}' did not match anything; check your search string '{}'",
-                                target_file.display().to_string(),
-                                search_str
-                            )
-                        }
-                    }
+                (
+                    TargetAction::Copy,
+                    transaction.read(&target_file).ok(),
+                    new_content,
+                )
+            } else if let Some(write_node) = write_node {
+                let file_content = write_node.get_string();
+                let new_content = eval_string_with_context(&file_content, context)
+                    .map_err(|e| script_error!(e.to_string(), write_node))?;
+
+                (
+                    TargetAction::Write,
+                    transaction.read(&target_file).ok(),
+                    new_content.into_bytes(),
+                )
+            } else {
+                return Err(script_error!(
+                    "Target must contain 'updates', 'toml', 'json', 'yaml', 'xml', 'write', 'changelog' or 'copyFrom'",
+                    target_node
+                ));
+            };
+
+            target_report.files.push(FileReport::new(
+                target_file.clone(),
+                base_dir,
+                action,
+                update_reports,
+                old_content.as_deref(),
+                &new_content,
+            ));
+            transaction.stage(&target_file, new_content);
+        }
 
-                    if update {
-                        fs::write(&target_file, content).map_err(|_| {
-                            script_error!(
-                                format!(
-                                    "Unable to write to file '{}'",
-                                    target_file.display().to_string()
-                                ),
-                                target_file_node
-                            )
-                        })?;
-                        action += "Updated";
-                    } else {
-                        action += "Would update";
+        Ok(target_report)
+    }
+
+    /// Evaluate the optional `when` entry of a target or `updates` entry, returning why it is skipped, or `None`
+    /// if it should be processed.
+    fn when_skip_reason(
+        &self,
+        node: &JsonNode,
+        context: &HashMapContext,
+    ) -> Result<Option<String>, ScriptError> {
+        let Ok(when_node) = node.get_object_entry("when") else {
+            return Ok(None);
+        };
+        let when_str = when_node.get_string();
+
+        match evalexpr::eval_boolean_with_context(&when_str, context) {
+            Ok(true) => Ok(None),
+            Ok(false) => Ok(Some(format!("'when' expression '{}' is false", when_str))),
+            Err(e) => Err(script_error!(
+                format!("'when' must be a boolean expression - {}", e),
+                when_node
+            )),
+        }
+    }
+
+    /// Read the text content of an existing target file, including any changes already staged.
+    fn read_target_file(
+        &self,
+        transaction: &Transaction,
+        target_file: &Path,
+        target_file_node: &JsonNode,
+    ) -> Result<String, ScriptError> {
+        transaction
+            .read(target_file)
+            .ok()
+            .and_then(|bytes| String::from_utf8(bytes).ok())
+            .ok_or_else(|| {
+                script_error!(
+                    format!(
+                        "File '{}' does not exist or is not readable",
+                        target_file.display()
+                    ),
+                    target_file_node
+                )
+            })
+    }
+
+    /// Apply the `updates` search/replace entries of a target to the content of a file.
+    fn update_content(
+        &self,
+        target_file: &Path,
+        content: &str,
+        updates_node: &JsonNode,
+        context: &mut HashMapContext,
+        update_reports: &mut Vec<UpdateReport>,
+    ) -> Result<String, ScriptError> {
+        let mut content = content.to_string();
+
+        for replacement_node in updates_node.get_array_iter()? {
+            let search_node = replacement_node.get_object_entry("search")?;
+            let search_str = search_node.get_string();
+
+            if let Some(skip_reason) = self.when_skip_reason(replacement_node, context)? {
+                update_reports.push(UpdateReport {
+                    search: search_str,
+                    match_count: 0,
+                    skip_reason: Some(skip_reason),
+                });
+                continue;
+            }
+
+            let re = RegexBuilder::new(&search_str)
+                .multi_line(true)
+                .build()
+                .map_err(|e| script_error!(format!("Regex is not valid - {}", e), search_node))?;
+            let replace_node = replacement_node.get_object_entry("replace")?;
+            let replace_str = &replace_node.get_string();
+            let mut match_count = 0;
+            let mut replace_err: Option<EvalexprError> = None;
+
+            content = re
+                .replace_all(&content, |caps: &Captures| {
+                    match_count += 1;
+
+                    if let Some(m) = caps.name("begin") {
+                        context
+                            .set_value("begin".to_owned(), Value::from(m.as_str()))
+                            .unwrap();
                     }
-                } else if let Some(copy_from_node) = copy_from_node {
-                    if update {
-                        let copy_from_str = copy_from_node.get_string();
-                        let s = eval_string_with_context(&copy_from_str, context)
-                            .map_err(|e| script_error!(e.to_string(), copy_from_node))?;
-                        let from_file = version_file_dir.join(s);
-
-                        fs::copy(&from_file, &target_file).map_err(|_| {
-                            script_error!(
-                                format!(
-                                    "unable to copy {} to {}",
-                                    from_file.display().to_string(),
-                                    target_file.display().to_string(),
-                                ),
-                                copy_from_node
-                            )
-                        })?;
-                        action += "Copied";
-                    } else {
-                        action += "Would copy"
+                    if let Some(m) = caps.name("end") {
+                        context
+                            .set_value("end".to_owned(), Value::from(m.as_str()))
+                            .unwrap();
                     }
-                } else if let Some(write_node) = write_node {
-                    if update {
-                        let file_content = write_node.get_string();
-
-                        fs::write(
-                            &target_file,
-                            eval_string_with_context(&file_content, context)
-                                .map_err(|e| script_error!(e.to_string(), write_node))?,
-                        )
-                        .map_err(|_| {
-                            script_error!(
-                                format!("Unable to write '{}'", target_file.display().to_string(),),
-                                write_node
-                            )
-                        })?;
-                        action += "Wrote";
-                    } else {
-                        action += "Would write";
+                    let result = eval_string_with_context(replace_str, context);
+
+                    match result {
+                        Ok(s) => s,
+                        Err(_) => {
+                            replace_err = result.err();
+                            String::new()
+                        }
                     }
-                }
+                })
+                .into_owned();
 
-                log::info!(
-                    "{} '{}' -> '{}'",
-                    action,
-                    target_node.get_object_entry("description")?.get_string(),
-                    target_file.display().to_string()
-                );
+            if let Some(err) = replace_err {
+                return Err(script_error!(err.to_string(), replace_node));
             }
+
+            if match_count == 0 {
+                log::warn!(
+                    "Search/replace in '{}' did not match anything; check your search string '{}'",
+                    target_file.display(),
+                    search_str
+                )
+            }
+
+            update_reports.push(UpdateReport {
+                search: search_str,
+                match_count,
+                skip_reason: None,
+            });
         }
-        Ok(())
+
+        Ok(content)
     }
 
-    /// Update the script file with the given content and root node.
+    /// Update the `vars` in the script file with the values from the run context, returning a report of the change.
+    ///
+    /// The script file is only written if `update` is true. Use [`StampVerTool::stage_script_files`] to write it
+    /// in the same transaction as the targets.
     pub fn update_script_file(
-        self: &Self,
+        &self,
         script_file: &Path,
         content: String,
         root_node: &JsonNode,
         run_context: &HashMapContext,
         update: bool,
+    ) -> Result<FileReport, ScriptError> {
+        let mut transaction = Transaction::new();
+        let mut file_reports = self.stage_script_files(
+            script_file,
+            content,
+            &[(root_node, run_context)],
+            &mut transaction,
+        )?;
+
+        if update {
+            transaction.commit()?;
+        }
+
+        let mut file_report = file_reports.remove(0);
+
+        file_report.applied = update;
+
+        Ok(file_report)
+    }
+
+    /// Update the `vars` in the script file and the files it `extends` or `include`s with the values from
+    /// the run context of each run, e.g. one for each component, returning a report for each file with the
+    /// script file first.
+    ///
+    /// Each var is written back to the file that defines it. The new content is staged in the transaction
+    /// and is not written until it is committed.
+    pub fn stage_script_files(
+        &self,
+        script_file: &Path,
+        content: String,
+        runs: &[(&JsonNode, &HashMapContext)],
+        transaction: &mut Transaction,
+    ) -> Result<Vec<FileReport>, ScriptError> {
+        let mut files: Vec<ScriptFileEdits> = vec![(script_file.to_path_buf(), content, vec![])];
+        let base_dir = match runs.first() {
+            Some((root_node, _)) => self.sources(root_node).base_dir(script_file),
+            None => script_file.parent().unwrap_or(Path::new(".")).to_path_buf(),
+        };
+
+        for (root_node, run_context) in runs.iter() {
+            self.add_var_edits(root_node, run_context, transaction, &mut files)?;
+        }
+
+        let mut file_reports = vec![];
+
+        for (path, file_content, edits) in files.into_iter() {
+            let new_content = json_edit::apply_edits(&file_content, edits);
+
+            file_reports.push(FileReport::new(
+                path.clone(),
+                &base_dir,
+                TargetAction::Update,
+                vec![],
+                Some(file_content.as_bytes()),
+                new_content.as_bytes(),
+            ));
+            transaction.stage(&path, new_content.into_bytes());
+        }
+
+        Ok(file_reports)
+    }
+
+    /// Add an edit to the content of the file that defines each var whose value in the run context differs
+    /// from its value in the script, adding the files that the script `extends` or `include`s as needed.
+    fn add_var_edits(
+        &self,
+        root_node: &JsonNode,
+        run_context: &HashMapContext,
+        transaction: &Transaction,
+        files: &mut Vec<ScriptFileEdits>,
     ) -> Result<(), ScriptError> {
-        let mut new_content = Cow::from(content).into_owned();
+        let sources = self.sources(root_node);
         let vars_node = root_node.get_object_entry("vars")?;
 
         for (identifier, var_node) in vars_node.get_object_iter()? {
+            if identifier == "tz" && self.time_zone.is_some() {
+                continue;
+            }
+
+            if self.is_overridden(identifier) && !self.persist_var_overrides {
+                continue;
+            }
+
             if let Some(value) = run_context.get_value(identifier) {
-                let s = match value {
-                    Value::String(s) => {
-                        if s.contains("\"") {
-                            format!("'{}'", s)
-                        } else {
-                            format!("\"{}\"", s)
-                        }
-                    }
-                    Value::Float(f) => format!("{}", f),
-                    Value::Boolean(b) => format!("{}", b),
-                    Value::Int(n) => format!("{}", n),
-                    _ => "".to_string(),
-                };
-                let re = RegexBuilder::new(
-                    &("(?P<begin>vars:\\s*\\{\n(?:.*\n)*?\\s*".to_string()
-                        + &identifier
-                        + "\\s*:\\s*).*?(?P<end>\\s*,?\\s*?\n)"),
-                )
-                .multi_line(true)
-                .build()
-                .map_err(|_| {
-                    script_error!(format!("Unable to replace var '{}'", identifier), var_node)
-                })?;
+                if *value == var_node.get_value() {
+                    continue;
+                }
 
-                new_content = re
-                    .replace(&new_content, "${begin}".to_string() + &s + "${end}")
-                    .into_owned();
+                self.add_var_edit(
+                    sources.var_file(identifier),
+                    identifier,
+                    var_node,
+                    value,
+                    transaction,
+                    files,
+                )
+                .map_err(|e| sources.locate(ScriptPart::Entry("vars", identifier), e))?;
             }
         }
 
-        if update {
-            fs::write(&script_file, &new_content)
-                .map_err(|err| script_error!(err.to_string(), root_node))?;
+        Ok(())
+    }
+
+    /// Add an edit to the content of the file that defines a var, replacing its value. The file is `None` for
+    /// the script file.
+    fn add_var_edit(
+        &self,
+        var_file: Option<&Path>,
+        identifier: &str,
+        var_node: &JsonNode,
+        value: &Value,
+        transaction: &Transaction,
+        files: &mut Vec<ScriptFileEdits>,
+    ) -> Result<(), ScriptError> {
+        let s = json_edit::format_value(value).ok_or_else(|| {
+            script_error!(
+                format!(
+                    "Var '{}' must be a string, integer, float or boolean to be saved",
+                    identifier
+                ),
+                var_node
+            )
+        })?;
+        let index = match var_file {
+            None => 0,
+            Some(path) => match files
+                .iter()
+                .position(|(file_path, _, _)| *file_path == path)
+            {
+                Some(index) => index,
+                None => {
+                    let file_content = transaction
+                        .read(path)
+                        .ok()
+                        .and_then(|bytes| String::from_utf8(bytes).ok())
+                        .ok_or_else(|| {
+                            script_error!(
+                                format!("Unable to read script file '{}'", path.display()),
+                                var_node
+                            )
+                        })?;
+
+                    files.push((path.to_path_buf(), file_content, vec![]));
+                    files.len() - 1
+                }
+            },
+        };
+        let (_, file_content, edits) = &mut files[index];
+        let span = var_node
+            .get_location()
+            .and_then(|location| json_edit::scalar_span_at(file_content, location))
+            .ok_or_else(|| {
+                script_error!(format!("Unable to replace var '{}'", identifier), var_node)
+            })?;
+
+        match edits.iter().find(|(edit_span, _)| *edit_span == span) {
+            Some((_, edit_s)) if *edit_s == s => (),
+            Some(_) => {
+                return Err(script_error!(
+                    format!(
+                        "Var '{}' is changed to different values by more than one component",
+                        identifier
+                    ),
+                    var_node
+                ));
+            }
+            None => edits.push((span, s)),
         }
 
         Ok(())
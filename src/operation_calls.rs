@@ -0,0 +1,252 @@
+use crate::{
+    ScriptError,
+    json_node_extra::*,
+    operation_params, script_error,
+    script_files::{ScriptPart, ScriptSources},
+};
+use evalexpr::{
+    ContextWithMutableFunctions, EvalexprError, EvalexprResult, Function, HashMapContext, Node,
+    Operator, Value,
+};
+use json5_nodes::JsonNode;
+
+/// The function that runs another operation within an operation, e.g. `op::run("stampBuild")`.
+const OP_RUN: &str = "op::run";
+
+/// A top-level statement of an operation, one of the parts of its expression separated by `;`.
+enum Statement {
+    /// An expression that is evaluated.
+    Expression(Node),
+    /// A statement that is only `op::run("name")`, which runs the named operation.
+    Call(String),
+}
+
+/// Add an `op::run` function to an expression context that explains it can only be used in operations.
+///
+/// Calls within operations are run as statements and never reach the function, so it is only called from
+/// other expressions, such as `calcVars` or targets.
+pub(crate) fn add_functions(context: &mut HashMapContext) -> EvalexprResult<()> {
+    context.set_function(
+        OP_RUN.to_owned(),
+        Function::new(|_| {
+            Err(EvalexprError::CustomMessage(
+                "'op::run' can only be used in 'operations', with the name of the operation as a string"
+                    .to_string(),
+            ))
+        }),
+    )
+}
+
+/// Check that each `op::run` in an operation is a whole statement naming another operation without
+/// parameters, and that the operation does not run itself, directly or through other operations.
+pub(crate) fn validate(operations_node: &JsonNode, name: &str) -> Result<(), ScriptError> {
+    let operation_node = operations_node.get_object_entry(name)?;
+
+    for called_name in calls(operation_node)? {
+        let called_node = operations_node
+            .get_object_entry(&called_name)
+            .map_err(|_| {
+                script_error!(
+                    format!(
+                        "Operation '{}' calls operation '{}', which is not in 'operations'",
+                        name, called_name
+                    ),
+                    operation_node
+                )
+            })?;
+
+        if operation_params::has_params(called_node) {
+            return Err(script_error!(
+                format!(
+                    "Operation '{}' calls operation '{}', which takes parameters",
+                    name, called_name
+                ),
+                operation_node
+            ));
+        }
+    }
+
+    if let Some(cycle) = find_cycle(
+        operations_node,
+        name,
+        &mut vec![name.to_string()],
+        &mut vec![],
+    ) {
+        return Err(cycle_error(name, &cycle, operation_node));
+    }
+
+    Ok(())
+}
+
+/// Run the statements of an operation in order with the same vars, running the operations it calls at the
+/// point they are called. Errors are located at the operation that caused them.
+pub(crate) fn run(
+    operations_node: &JsonNode,
+    name: &str,
+    context: &mut HashMapContext,
+    sources: &ScriptSources,
+) -> Result<(), ScriptError> {
+    run_with_callers(operations_node, name, context, sources, &mut vec![])
+}
+
+fn run_with_callers(
+    operations_node: &JsonNode,
+    name: &str,
+    context: &mut HashMapContext,
+    sources: &ScriptSources,
+    callers: &mut Vec<String>,
+) -> Result<(), ScriptError> {
+    let locate = |e| sources.locate(ScriptPart::Entry("operations", name), e);
+    let operation_node = operations_node.get_object_entry(name).map_err(locate)?;
+
+    if callers.iter().any(|caller| caller == name) {
+        callers.push(name.to_string());
+
+        return Err(locate(cycle_error(name, callers, operation_node)));
+    }
+
+    let expr_node = operation_params::expr_node(operation_node).map_err(locate)?;
+
+    callers.push(name.to_string());
+
+    for statement in statements(operation_node).map_err(locate)? {
+        match statement {
+            Statement::Expression(node) => {
+                node.eval_with_context_mut(context)
+                    .map_err(|e| locate(script_error!(e.to_string(), expr_node)))?;
+            }
+            Statement::Call(called_name) => {
+                run_with_callers(operations_node, &called_name, context, sources, callers)?;
+            }
+        }
+    }
+
+    callers.pop();
+
+    Ok(())
+}
+
+/// Get the names of the operations that an operation calls, in order.
+fn calls(operation_node: &JsonNode) -> Result<Vec<String>, ScriptError> {
+    Ok(statements(operation_node)?
+        .into_iter()
+        .filter_map(|statement| match statement {
+            Statement::Call(called_name) => Some(called_name),
+            Statement::Expression(_) => None,
+        })
+        .collect())
+}
+
+/// Parse the expression of an operation into its top-level statements. It is an error to use `op::run`
+/// anywhere but as a whole statement with the name of an operation as a string, e.g. within `if` or as an
+/// argument, because every argument is evaluated.
+fn statements(operation_node: &JsonNode) -> Result<Vec<Statement>, ScriptError> {
+    let expr_node = operation_params::expr_node(operation_node)?;
+    let tree = evalexpr::build_operator_tree(&expr_node.get_string())
+        .map_err(|e| script_error!(e.to_string(), expr_node))?;
+    let root = unwrap_root(&tree);
+    let statement_nodes = match root.operator() {
+        Operator::Chain => root.children().iter().map(unwrap_root).collect(),
+        _ => vec![root],
+    };
+    let mut statements = vec![];
+
+    for node in statement_nodes {
+        if *node.operator() == Operator::RootNode && node.children().is_empty() {
+            continue;
+        }
+
+        let called_name = match (node.operator(), node.children()) {
+            (Operator::FunctionIdentifier { identifier }, [arg]) if identifier == OP_RUN => {
+                match unwrap_root(arg).operator() {
+                    Operator::Const {
+                        value: Value::String(called_name),
+                    } => Some(called_name.clone()),
+                    _ => None,
+                }
+            }
+            _ => None,
+        };
+
+        match called_name {
+            Some(called_name) => statements.push(Statement::Call(called_name)),
+            None if uses_op_run(node) => {
+                return Err(script_error!(
+                    "'op::run' must be a whole statement with the name of an operation as a string, e.g. 'patch += 1; op::run(\"stampBuild\")'",
+                    expr_node
+                ));
+            }
+            None => statements.push(Statement::Expression(node.clone())),
+        }
+    }
+
+    Ok(statements)
+}
+
+/// Does a node or any node within it call `op::run`?
+fn uses_op_run(node: &Node) -> bool {
+    matches!(node.operator(), Operator::FunctionIdentifier { identifier } if identifier == OP_RUN)
+        || node
+            .iter_function_identifiers()
+            .any(|identifier| identifier == OP_RUN)
+}
+
+/// Get the node within the root nodes that wrap the statements and arguments of an expression.
+fn unwrap_root(node: &Node) -> &Node {
+    match (node.operator(), node.children()) {
+        (Operator::RootNode, [child]) => unwrap_root(child),
+        _ => node,
+    }
+}
+
+/// Find the calls leading from the last operation in `path` back to the first, skipping operations that
+/// have been `visited` or that have errors of their own.
+fn find_cycle(
+    operations_node: &JsonNode,
+    name: &str,
+    path: &mut Vec<String>,
+    visited: &mut Vec<String>,
+) -> Option<Vec<String>> {
+    let operation_node = operations_node
+        .get_object_entry(path.last().unwrap())
+        .ok()?;
+
+    for called_name in calls(operation_node).ok()? {
+        if called_name == name {
+            path.push(called_name);
+
+            return Some(path.clone());
+        }
+
+        if visited.contains(&called_name) {
+            continue;
+        }
+
+        visited.push(called_name.clone());
+        path.push(called_name);
+
+        if let Some(cycle) = find_cycle(operations_node, name, path, visited) {
+            return Some(cycle);
+        }
+
+        path.pop();
+    }
+
+    None
+}
+
+/// Create the error for an operation that runs itself through the operations in `cycle`.
+fn cycle_error(name: &str, cycle: &[String], operation_node: &JsonNode) -> ScriptError {
+    script_error!(
+        format!(
+            "Operation '{}' calls itself through {}",
+            name,
+            cycle
+                .iter()
+                .map(|caller| format!("'{}'", caller))
+                .collect::<Vec<_>>()
+                .join(" -> ")
+        ),
+        operation_node
+    )
+}
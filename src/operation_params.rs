@@ -0,0 +1,374 @@
+use crate::{
+    ScriptError, json_node_extra::*, operation_calls, script_error, script_files::ScriptSources,
+};
+use evalexpr::{
+    Context, ContextWithMutableVariables, HashMapContext, IterateVariablesContext, Value,
+};
+use json5_nodes::JsonNode;
+
+/// The types a parameter can have, with the name used in the script and in error messages.
+const PARAM_TYPES: [(&str, &str); 4] = [
+    ("string", "a string"),
+    ("integer", "an integer"),
+    ("float", "a float"),
+    ("boolean", "a boolean"),
+];
+
+/// A parameter declared in the `params` of an operation.
+struct Param {
+    name: String,
+    param_type: &'static str,
+    default: Option<Value>,
+}
+
+/// Validate an operation, which is either an expression or an object with an `expr` expression and the
+/// `params` it takes.
+///
+/// A parameter is either a name, for a required string, or an object with a `name`, an optional `type` of
+/// `string`, `integer`, `float` or `boolean` and an optional `default` which makes it optional.
+pub(crate) fn validate(
+    name: &str,
+    operation_node: &JsonNode,
+    root_node: &JsonNode,
+) -> Result<(), ScriptError> {
+    if operation_node.is_string() {
+        return Ok(());
+    }
+
+    if !operation_node.is_object() {
+        return Err(script_error!(
+            format!("Operation '{}' must be a string or an object", name),
+            operation_node
+        ));
+    }
+
+    let expr_node = operation_node.get_object_entry("expr")?;
+
+    if !expr_node.is_string() {
+        return Err(script_error!("'expr' entry must be string", expr_node));
+    }
+
+    let params = params(operation_node)?;
+    let vars_node = root_node.get_object_entry("vars")?;
+    let calc_vars_node = root_node.get_object_entry("calcVars").ok();
+
+    for (index, (param, param_node)) in params.iter().enumerate() {
+        let is_identifier = param
+            .name
+            .chars()
+            .next()
+            .is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+            && param
+                .name
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || c == '_');
+
+        if !is_identifier {
+            return Err(script_error!(
+                format!("Parameter name '{}' is not a valid identifier", param.name),
+                *param_node
+            ));
+        }
+
+        if params[..index]
+            .iter()
+            .any(|(other, _)| other.name == param.name)
+        {
+            return Err(script_error!(
+                format!("Parameter '{}' is declared more than once", param.name),
+                *param_node
+            ));
+        }
+
+        if vars_node.get_object_entry(&param.name).is_ok()
+            || calc_vars_node.is_some_and(|node| node.get_object_entry(&param.name).is_ok())
+        {
+            return Err(script_error!(
+                format!(
+                    "Parameter '{}' has the same name as a var or calculated var",
+                    param.name
+                ),
+                *param_node
+            ));
+        }
+
+        if let Some(default) = &param.default
+            && convert(param.param_type, default).is_none()
+        {
+            return Err(script_error!(
+                format!(
+                    "'default' for parameter '{}' must be {}",
+                    param.name,
+                    type_description(param.param_type)
+                ),
+                *param_node
+            ));
+        }
+    }
+
+    Ok(())
+}
+
+/// Does an operation declare any parameters?
+pub(crate) fn has_params(operation_node: &JsonNode) -> bool {
+    operation_node
+        .get_object_entry("params")
+        .is_ok_and(|params_node| {
+            params_node
+                .get_array_iter()
+                .is_ok_and(|mut iter| iter.next().is_some())
+        })
+}
+
+/// Get the expression node of an operation.
+pub(crate) fn expr_node(operation_node: &JsonNode) -> Result<&JsonNode, ScriptError> {
+    if operation_node.is_object() {
+        operation_node.get_object_entry("expr")
+    } else {
+        Ok(operation_node)
+    }
+}
+
+/// Run an operation from the script with `name=value` arguments for its parameters.
+///
+/// The parameters are only visible to the operation, so they are not saved and do not appear in targets.
+/// The `sources` locate errors in the operations that it calls.
+pub(crate) fn run_operation(
+    operations_node: &JsonNode,
+    name: &str,
+    args: &[String],
+    context: &mut HashMapContext,
+    sources: &ScriptSources,
+) -> Result<(), ScriptError> {
+    let operation_node = operations_node.get_object_entry(name)?;
+    let param_values = bind(name, operation_node, args)?;
+
+    if param_values.is_empty() {
+        log::info!("Operation '{}'", name);
+
+        return operation_calls::run(operations_node, name, context, sources);
+    }
+
+    log::info!(
+        "Operation '{}' with {}",
+        name,
+        param_values
+            .iter()
+            .map(|(param_name, value)| format!("{} = {}", param_name, value))
+            .collect::<Vec<_>>()
+            .join(", ")
+    );
+
+    let mut operation_context = context.clone();
+
+    for (param_name, value) in param_values.iter() {
+        operation_context.set_value(param_name.clone(), value.clone())?;
+    }
+
+    operation_calls::run(operations_node, name, &mut operation_context, sources)?;
+
+    for (identifier, value) in operation_context.iter_variables() {
+        if !param_values
+            .iter()
+            .any(|(param_name, _)| *param_name == identifier)
+            && context.get_value(&identifier) != Some(&value)
+        {
+            context.set_value(identifier, value)?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Match `name=value` arguments to the parameters of an operation, converting each value to the type of
+/// its parameter and using the defaults of optional parameters that are not given.
+fn bind(
+    name: &str,
+    operation_node: &JsonNode,
+    args: &[String],
+) -> Result<Vec<(String, Value)>, ScriptError> {
+    let params = params(operation_node)?;
+
+    if params.is_empty() && !args.is_empty() {
+        return Err(script_error!(
+            format!("Operation '{}' does not take arguments", name),
+            operation_node
+        ));
+    }
+
+    let mut param_values: Vec<(String, Value)> = vec![];
+
+    for arg in args.iter() {
+        let (param_name, text) = arg.split_once('=').ok_or_else(|| {
+            script_error!(
+                format!(
+                    "Arguments for operation '{}' must be name=value, not '{}'",
+                    name, arg
+                ),
+                operation_node
+            )
+        })?;
+        let (param, _) = params
+            .iter()
+            .find(|(param, _)| param.name == param_name)
+            .ok_or_else(|| {
+                script_error!(
+                    format!(
+                        "Operation '{}' has no parameter '{}', expected {}",
+                        name,
+                        param_name,
+                        param_list(params.iter().map(|(param, _)| param))
+                    ),
+                    operation_node
+                )
+            })?;
+
+        if param_values.iter().any(|(other, _)| other == param_name) {
+            return Err(script_error!(
+                format!(
+                    "Parameter '{}' of operation '{}' is given more than once",
+                    param_name, name
+                ),
+                operation_node
+            ));
+        }
+
+        let value = parse(param.param_type, text).ok_or_else(|| {
+            script_error!(
+                format!(
+                    "Parameter '{}' of operation '{}' must be {}, not '{}'",
+                    param_name,
+                    name,
+                    type_description(param.param_type),
+                    text
+                ),
+                operation_node
+            )
+        })?;
+
+        param_values.push((param.name.clone(), value));
+    }
+
+    let mut missing_params = vec![];
+
+    for (param, _) in params.iter() {
+        if param_values.iter().any(|(other, _)| *other == param.name) {
+            continue;
+        }
+
+        match &param.default {
+            Some(default) => param_values.push((
+                param.name.clone(),
+                convert(param.param_type, default).unwrap_or_else(|| default.clone()),
+            )),
+            None => missing_params.push(param),
+        }
+    }
+
+    if !missing_params.is_empty() {
+        return Err(script_error!(
+            format!(
+                "Operation '{}' is missing parameter(s) {}",
+                name,
+                param_list(missing_params.into_iter())
+            ),
+            operation_node
+        ));
+    }
+
+    Ok(param_values)
+}
+
+/// Read the `params` of an operation, with the node that declares each one.
+fn params(operation_node: &JsonNode) -> Result<Vec<(Param, &JsonNode)>, ScriptError> {
+    let Ok(params_node) = operation_node.get_object_entry("params") else {
+        return Ok(vec![]);
+    };
+    let mut params = vec![];
+
+    for param_node in params_node.get_array_iter()? {
+        let param = if param_node.is_string() {
+            Param {
+                name: param_node.get_string(),
+                param_type: PARAM_TYPES[0].0,
+                default: None,
+            }
+        } else if param_node.is_object() {
+            let name_node = param_node.get_object_entry("name")?;
+
+            if !name_node.is_string() {
+                return Err(script_error!("'name' entry must be string", name_node));
+            }
+
+            let param_type = match param_node.get_object_entry("type") {
+                Ok(type_node) => PARAM_TYPES
+                    .iter()
+                    .map(|(param_type, _)| *param_type)
+                    .find(|param_type| {
+                        type_node.is_string() && type_node.get_string() == *param_type
+                    })
+                    .ok_or_else(|| {
+                        script_error!(
+                            "'type' entry must be 'string', 'integer', 'float' or 'boolean'",
+                            type_node
+                        )
+                    })?,
+                Err(_) => PARAM_TYPES[0].0,
+            };
+
+            Param {
+                name: name_node.get_string(),
+                param_type,
+                default: param_node
+                    .get_object_entry("default")
+                    .ok()
+                    .map(|default_node| default_node.get_value()),
+            }
+        } else {
+            return Err(script_error!(
+                "'params' entry must be a string or an object",
+                param_node
+            ));
+        };
+
+        params.push((param, param_node));
+    }
+
+    Ok(params)
+}
+
+/// Parse the text of an argument as a value of a parameter type.
+fn parse(param_type: &str, text: &str) -> Option<Value> {
+    match param_type {
+        "integer" => text.parse().ok().map(Value::Int),
+        "float" => text.parse().ok().map(Value::Float),
+        "boolean" => text.parse().ok().map(Value::Boolean),
+        _ => Some(Value::from(text)),
+    }
+}
+
+/// Convert a default value to a parameter type, allowing an integer default for a float parameter.
+fn convert(param_type: &str, value: &Value) -> Option<Value> {
+    match (param_type, value) {
+        ("string", Value::String(_))
+        | ("integer", Value::Int(_))
+        | ("float", Value::Float(_))
+        | ("boolean", Value::Boolean(_)) => Some(value.clone()),
+        ("float", Value::Int(n)) => Some(Value::Float(*n as f64)),
+        _ => None,
+    }
+}
+
+fn type_description(param_type: &str) -> &'static str {
+    PARAM_TYPES
+        .iter()
+        .find(|(name, _)| *name == param_type)
+        .map_or("a string", |(_, description)| description)
+}
+
+fn param_list<'a>(params: impl Iterator<Item = &'a Param>) -> String {
+    params
+        .map(|param| format!("'{}'", param.name))
+        .collect::<Vec<_>>()
+        .join(", ")
+}
@@ -0,0 +1,409 @@
+use crate::{ScriptError, json_node_extra::*, script_error};
+use evalexpr::{Context, ContextWithMutableVariables, HashMapContext, Value};
+use json5_nodes::JsonNode;
+
+/// The built-in operations available when the script has a `prerelease` section.
+pub(crate) const OPERATIONS: [&str; 3] = ["nextPrerelease", "promote", "release"];
+
+/// The vars holding the version when the `prerelease` section has no `versionVars`.
+const DEFAULT_VERSION_VARS: [&str; 3] = ["major", "minor", "patch"];
+
+/// The values of the version, channel and number vars before a script operation runs.
+pub(crate) struct VarValues {
+    version: Vec<Option<Value>>,
+    channel: Option<Value>,
+    number: Option<Value>,
+}
+
+/// The prerelease lifecycle declared in the `prerelease` section of a script.
+struct Lifecycle<'a> {
+    node: &'a JsonNode,
+    operation: &'a str,
+    position: usize,
+    channels: Vec<String>,
+    channel_var: String,
+    number_var: String,
+}
+
+/// Validate the `prerelease` section of a script, if there is one.
+pub(crate) fn validate(root_node: &JsonNode) -> Result<(), ScriptError> {
+    let Ok(prerelease_node) = root_node.get_object_entry("prerelease") else {
+        return Ok(());
+    };
+
+    if !prerelease_node.is_object() {
+        return Err(script_error!(
+            "'prerelease' must be an object",
+            prerelease_node
+        ));
+    }
+
+    let channels_node = prerelease_node.get_object_entry("channels")?;
+    let mut channels: Vec<String> = vec![];
+
+    for channel_node in channels_node.get_array_iter()? {
+        let channel = channel_node.get_string();
+
+        if !channel_node.is_string()
+            || channel.is_empty()
+            || !channel
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || c == '-')
+        {
+            return Err(script_error!(
+                "'channels' entry must be a string of letters, digits and hyphens",
+                channel_node
+            ));
+        }
+
+        if channels.contains(&channel) {
+            return Err(script_error!(
+                format!("Channel '{}' is listed more than once", channel),
+                channel_node
+            ));
+        }
+
+        channels.push(channel);
+    }
+
+    if channels.is_empty() {
+        return Err(script_error!(
+            "'channels' must list at least one channel",
+            channels_node
+        ));
+    }
+
+    let vars_node = root_node.get_object_entry("vars")?;
+
+    for (entry, is_type, type_name) in [
+        (
+            "channelVar",
+            JsonNode::is_string as fn(&JsonNode) -> bool,
+            "string",
+        ),
+        ("numberVar", JsonNode::is_integer, "integer"),
+    ] {
+        let var_name_node = prerelease_node.get_object_entry(entry)?;
+
+        if !var_name_node.is_string() {
+            return Err(script_error!(
+                format!("'{}' must be a string", entry),
+                var_name_node
+            ));
+        }
+
+        let var_name = var_name_node.get_string();
+
+        if !vars_node.get_object_entry(&var_name).is_ok_and(is_type) {
+            return Err(script_error!(
+                format!("'{}' must name a {} entry in 'vars'", entry, type_name),
+                var_name_node
+            ));
+        }
+    }
+
+    let operations_node = root_node.get_object_entry("operations")?;
+
+    if let Ok(start_operation_node) = prerelease_node.get_object_entry("startOperation")
+        && !(start_operation_node.is_string()
+            && operations_node
+                .get_object_entry(&start_operation_node.get_string())
+                .is_ok())
+    {
+        return Err(script_error!(
+            "'startOperation' must name an entry in 'operations'",
+            start_operation_node
+        ));
+    }
+
+    if let Ok(version_vars_node) = prerelease_node.get_object_entry("versionVars") {
+        for version_var_node in version_vars_node.get_array_iter()? {
+            if !(version_var_node.is_string()
+                && vars_node
+                    .get_object_entry(&version_var_node.get_string())
+                    .is_ok())
+            {
+                return Err(script_error!(
+                    "'versionVars' entry must name an entry in 'vars'",
+                    version_var_node
+                ));
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Check that an `operations` entry does not have the name of a built-in operation when there is a
+/// `prerelease` section.
+pub(crate) fn validate_operation_name(
+    root_node: &JsonNode,
+    name: &str,
+    operation_node: &JsonNode,
+) -> Result<(), ScriptError> {
+    if OPERATIONS.contains(&name) && root_node.get_object_entry("prerelease").is_ok() {
+        return Err(script_error!(
+            format!(
+                "Operation '{}' is built in when there is a 'prerelease' section",
+                name
+            ),
+            operation_node
+        ));
+    }
+
+    Ok(())
+}
+
+/// Run a built-in prerelease operation, returning `false` if the operation is not one of them.
+///
+/// `position` is the position of the operation in the operations being run, starting from 1, for errors.
+/// `nextPrerelease` takes an optional channel. Without one, or with the current channel, it increments the
+/// prerelease number. With a later channel it moves to that channel with a number of 1. When the version is
+/// released it first runs the `startOperation`, if any, to bump the version. `promote` moves to the next
+/// channel and `release` clears the channel. Moving back to an earlier channel is an error.
+///
+/// The `startOperation` is run with `run_start_operation`, so that its errors are those of the operation.
+pub(crate) fn run_operation(
+    operation: &str,
+    position: usize,
+    args: &[String],
+    root_node: &JsonNode,
+    context: &mut HashMapContext,
+    run_start_operation: impl FnOnce(&str, &mut HashMapContext) -> Result<(), ScriptError>,
+) -> Result<bool, ScriptError> {
+    let Ok(prerelease_node) = root_node.get_object_entry("prerelease") else {
+        return Ok(false);
+    };
+
+    if !OPERATIONS.contains(&operation) {
+        return Ok(false);
+    }
+
+    let lifecycle = Lifecycle::new(prerelease_node, operation, position)?;
+    let (channel, number) = lifecycle.current(context)?;
+    let max_args = if operation == "nextPrerelease" { 1 } else { 0 };
+
+    if args.len() > max_args {
+        return Err(lifecycle.error(format!(
+            "Operation '{}' takes {} argument(s), but {} were given",
+            operation,
+            max_args,
+            args.len()
+        )));
+    }
+
+    let (new_channel, new_number) = match (operation, channel, args.first()) {
+        ("nextPrerelease", None, None) => {
+            return Err(lifecycle.error(format!(
+                "The version is released, so 'nextPrerelease' needs a channel, one of {}",
+                lifecycle.channel_list()
+            )));
+        }
+        ("nextPrerelease", Some(index), None) => (Some(index), number + 1),
+        ("nextPrerelease", channel, Some(new_channel)) => {
+            let new_index = lifecycle.channel_index(new_channel).ok_or_else(|| {
+                lifecycle.error(format!(
+                    "Unknown prerelease channel '{}', expected one of {}",
+                    new_channel,
+                    lifecycle.channel_list()
+                ))
+            })?;
+
+            match channel {
+                Some(index) if new_index < index => {
+                    return Err(lifecycle.error(format!(
+                        "Cannot move from prerelease channel '{}' back to '{}'",
+                        lifecycle.channels[index], new_channel
+                    )));
+                }
+                Some(index) if new_index == index => (Some(index), number + 1),
+                None => {
+                    if let Ok(start_operation_node) =
+                        prerelease_node.get_object_entry("startOperation")
+                    {
+                        run_start_operation(&start_operation_node.get_string(), context)?;
+                    }
+
+                    (Some(new_index), 1)
+                }
+                _ => (Some(new_index), 1),
+            }
+        }
+        (_, None, _) => {
+            return Err(lifecycle.error(format!(
+                "Cannot '{}' because the version is already released",
+                operation
+            )));
+        }
+        ("promote", Some(index), _) => {
+            if index + 1 == lifecycle.channels.len() {
+                return Err(lifecycle.error(format!(
+                    "Cannot promote from '{}' because it is the last channel, use 'release' instead",
+                    lifecycle.channels[index]
+                )));
+            }
+
+            (Some(index + 1), 1)
+        }
+        (_, Some(_), _) => (None, 0),
+    };
+
+    context.set_value(
+        lifecycle.channel_var.clone(),
+        Value::from(
+            new_channel
+                .map(|index| lifecycle.channels[index].as_str())
+                .unwrap_or_default(),
+        ),
+    )?;
+    context.set_value(lifecycle.number_var.clone(), Value::Int(new_number))?;
+
+    Ok(true)
+}
+
+/// Get the values of the version, channel and number vars before a script operation runs, or `None` if the
+/// script has no `prerelease` section.
+pub(crate) fn vars(root_node: &JsonNode, context: &HashMapContext) -> Option<VarValues> {
+    let prerelease_node = root_node.get_object_entry("prerelease").ok()?;
+    let value = |entry: &str| {
+        prerelease_node
+            .get_object_entry(entry)
+            .ok()
+            .and_then(|node| context.get_value(&node.get_string()).cloned())
+    };
+
+    Some(VarValues {
+        version: version_vars(prerelease_node)
+            .iter()
+            .map(|name| context.get_value(name).cloned())
+            .collect(),
+        channel: value("channelVar"),
+        number: value("numberVar"),
+    })
+}
+
+/// Reset the prerelease number to 1 after a script operation changes the version during a prerelease, so
+/// `1.3.0-beta.3` followed by `incrPatch` is `1.3.1-beta.1`, not `1.3.1-beta.3`.
+///
+/// The version is in the `versionVars`, which default to `major`, `minor` and `patch`, and is compared with
+/// its value before the operation ran, so changes made through `op::run` are included. An operation that
+/// sets the channel or number itself is left alone.
+pub(crate) fn reset_after_bump(
+    operation: &str,
+    vars_before: Option<VarValues>,
+    root_node: &JsonNode,
+    context: &mut HashMapContext,
+) -> Result<(), ScriptError> {
+    let (Some(vars_before), Some(vars_after), Ok(prerelease_node)) = (
+        vars_before,
+        vars(root_node, context),
+        root_node.get_object_entry("prerelease"),
+    ) else {
+        return Ok(());
+    };
+
+    if vars_after.version == vars_before.version
+        || vars_after.channel != vars_before.channel
+        || vars_after.number != vars_before.number
+    {
+        return Ok(());
+    }
+
+    let lifecycle = Lifecycle::new(prerelease_node, operation, 0)?;
+
+    if let (Some(index), _) = lifecycle.current(context)? {
+        log::info!(
+            "Operation '{}' changed the version during the '{}' prerelease, so '{}' is reset to 1",
+            operation,
+            lifecycle.channels[index],
+            lifecycle.number_var
+        );
+        context.set_value(lifecycle.number_var.clone(), Value::Int(1))?;
+    }
+
+    Ok(())
+}
+
+/// The names of the vars holding the version, from `versionVars` or else `major`, `minor` and `patch`.
+fn version_vars(prerelease_node: &JsonNode) -> Vec<String> {
+    match prerelease_node.get_object_entry("versionVars") {
+        Ok(version_vars_node) => version_vars_node
+            .get_array_iter()
+            .map(|iter| iter.map(|node| node.get_string()).collect())
+            .unwrap_or_default(),
+        Err(_) => DEFAULT_VERSION_VARS
+            .iter()
+            .map(|name| name.to_string())
+            .collect(),
+    }
+}
+
+impl<'a> Lifecycle<'a> {
+    fn new(
+        node: &'a JsonNode,
+        operation: &'a str,
+        position: usize,
+    ) -> Result<Lifecycle<'a>, ScriptError> {
+        Ok(Lifecycle {
+            node,
+            operation,
+            position,
+            channels: node
+                .get_object_entry("channels")?
+                .get_array_iter()?
+                .map(|node| node.get_string())
+                .collect(),
+            channel_var: node.get_object_entry("channelVar")?.get_string(),
+            number_var: node.get_object_entry("numberVar")?.get_string(),
+        })
+    }
+
+    /// Get the current channel index, or `None` if the version is released, and the prerelease number.
+    fn current(&self, context: &HashMapContext) -> Result<(Option<usize>, i64), ScriptError> {
+        let channel = match context.get_value(&self.channel_var) {
+            Some(Value::String(channel)) if channel.is_empty() => None,
+            Some(Value::String(channel)) => Some(self.channel_index(channel).ok_or_else(|| {
+                self.error(format!(
+                    "Current prerelease channel '{}' in '{}' is not one of {}",
+                    channel,
+                    self.channel_var,
+                    self.channel_list()
+                ))
+            })?),
+            _ => {
+                return Err(self.error(format!("'{}' must be a string", self.channel_var)));
+            }
+        };
+        let number = match context.get_value(&self.number_var) {
+            Some(Value::Int(number)) => *number,
+            _ => {
+                return Err(self.error(format!("'{}' must be an integer", self.number_var)));
+            }
+        };
+
+        Ok((channel, number))
+    }
+
+    fn channel_index(&self, channel: &str) -> Option<usize> {
+        self.channels.iter().position(|c| c == channel)
+    }
+
+    fn channel_list(&self) -> String {
+        self.channels
+            .iter()
+            .map(|channel| format!("'{}'", channel))
+            .collect::<Vec<_>>()
+            .join(", ")
+    }
+
+    /// Create an error for the operation, which has no node of its own, located at the `prerelease` section.
+    fn error(&self, message: String) -> ScriptError {
+        script_error!(
+            format!(
+                "{} (operation '{}' at position {})",
+                message, self.operation, self.position
+            ),
+            self.node
+        )
+    }
+}
@@ -0,0 +1,127 @@
+use crate::{ScriptError, git, json_node_extra::*, script_error};
+use evalexpr::HashMapContext;
+use json5_nodes::JsonNode;
+use std::path::{Path, PathBuf};
+
+#[derive(Clone, Debug, PartialEq)]
+/// The tag and message for a release, from the `release` section of a script file.
+pub struct Release {
+    /// The name of the tag, e.g. `v1.2.3`.
+    pub tag: String,
+    /// The commit and tag message.
+    pub message: String,
+}
+
+/// Validate the `release` section of a script, if there is one.
+pub(crate) fn validate(root_node: &JsonNode) -> Result<(), ScriptError> {
+    let Ok(release_node) = root_node.get_object_entry("release") else {
+        return Ok(());
+    };
+
+    if !release_node.is_object() {
+        return Err(script_error!("'release' must be an object", release_node));
+    }
+
+    let tag_node = release_node.get_object_entry("tag")?;
+
+    if !tag_node.is_string() {
+        return Err(script_error!("'tag' must be a string", tag_node));
+    }
+
+    if let Ok(message_node) = release_node.get_object_entry("message")
+        && !message_node.is_string()
+    {
+        return Err(script_error!("'message' must be a string", message_node));
+    }
+
+    Ok(())
+}
+
+/// Evaluate the `tag` and `message` expressions of the `release` section, if there is one.
+///
+/// The message defaults to the tag.
+pub(crate) fn evaluate(
+    root_node: &JsonNode,
+    context: &HashMapContext,
+) -> Result<Option<Release>, ScriptError> {
+    let Ok(release_node) = root_node.get_object_entry("release") else {
+        return Ok(None);
+    };
+    let evaluate_string = |node: &JsonNode| {
+        evalexpr::eval_string_with_context(&node.get_string(), context)
+            .map_err(|e| script_error!(e.to_string(), node))
+    };
+    let tag_node = release_node.get_object_entry("tag")?;
+    let tag = evaluate_string(tag_node)?;
+
+    if tag.is_empty() {
+        return Err(script_error!("'tag' must not be empty", tag_node));
+    }
+
+    let message = match release_node.get_object_entry("message") {
+        Ok(message_node) => evaluate_string(message_node)?,
+        Err(_) => tag.clone(),
+    };
+
+    Ok(Some(Release { tag, message }))
+}
+
+/// Check that the release can be tagged in the repository, because the tag is valid and does not exist.
+pub(crate) fn check_tag(repo_dir: &Path, release: &Release) -> Result<(), String> {
+    let tag_ref = format!("refs/tags/{}", release.tag);
+
+    if git::run(repo_dir, &["check-ref-format", &tag_ref]).is_err() {
+        return Err(format!("'{}' is not a valid tag name", release.tag));
+    }
+
+    git::run(repo_dir, &["rev-parse", "--git-dir"])?;
+
+    if git::run(repo_dir, &["rev-parse", "--quiet", "--verify", &tag_ref]).is_ok() {
+        return Err(format!("Tag '{}' already exists", release.tag));
+    }
+
+    Ok(())
+}
+
+/// Commit exactly the given files with the release message, and optionally create an annotated tag.
+pub(crate) fn commit(
+    repo_dir: &Path,
+    release: &Release,
+    paths: &[PathBuf],
+    tag: bool,
+) -> Result<(), String> {
+    if paths.is_empty() {
+        return Err("There are no changed files to commit".to_string());
+    }
+
+    let mut args = vec!["add".into(), "--".into()];
+
+    args.extend(paths.iter().map(|path| path.as_os_str().to_owned()));
+    git::run(repo_dir, &args)?;
+
+    args.splice(
+        0..1,
+        [
+            "commit".into(),
+            "--quiet".into(),
+            "-m".into(),
+            release.message.clone().into(),
+        ],
+    );
+    git::run(repo_dir, &args)?;
+    log::info!(
+        "Committed {} file(s) with message '{}'",
+        paths.len(),
+        release.message
+    );
+
+    if tag {
+        git::run(
+            repo_dir,
+            &["tag", "-a", &release.tag, "-m", &release.message],
+        )?;
+        log::info!("Created tag '{}'", release.tag);
+    }
+
+    Ok(())
+}
@@ -0,0 +1,193 @@
+use crate::diff;
+use sha2::{Digest, Sha256};
+use std::path::{Path, PathBuf};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+/// The action performed on a target file.
+pub enum TargetAction {
+    /// Search and replace within an existing file.
+    Update,
+    /// Set values by key path within an existing TOML file.
+    Toml,
+    /// Set values by JSON Pointer or key path within an existing JSON or JSON5 file.
+    Json,
+    /// Set scalar values by key path within an existing YAML file.
+    Yaml,
+    /// Set element text or attribute values by path within an existing XML file.
+    Xml,
+    /// Write the result of an expression to the file.
+    Write,
+    /// Copy the file from another file.
+    Copy,
+    /// Add a section for the version to a Markdown changelog, creating it if necessary.
+    Changelog,
+}
+
+impl TargetAction {
+    /// Describe the action, e.g. "Updated" or "Would update".
+    pub fn describe(&self, applied: bool) -> &'static str {
+        match (self, applied) {
+            (TargetAction::Write, true) => "Wrote",
+            (TargetAction::Write, false) => "Would write",
+            (TargetAction::Copy, true) => "Copied",
+            (TargetAction::Copy, false) => "Would copy",
+            (_, true) => "Updated",
+            (_, false) => "Would update",
+        }
+    }
+
+    /// The name of the target entry in the script file for the action, e.g. `updates` or `toml`.
+    pub fn entry_name(&self) -> &'static str {
+        match self {
+            TargetAction::Update => "updates",
+            TargetAction::Toml => "toml",
+            TargetAction::Json => "json",
+            TargetAction::Yaml => "yaml",
+            TargetAction::Xml => "xml",
+            TargetAction::Write => "write",
+            TargetAction::Copy => "copyFrom",
+            TargetAction::Changelog => "changelog",
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+/// The result of a single `updates` entry applied to a file.
+pub struct UpdateReport {
+    /// The search regular expression, or the key path for structured file actions.
+    pub search: String,
+    /// The number of times the search expression or key path matched.
+    pub match_count: usize,
+    /// Why the entry was skipped, e.g. because its `when` expression is false, or `None` if it was applied.
+    pub skip_reason: Option<String>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+/// The result of processing a single target file.
+pub struct FileReport {
+    /// The absolute path of the file.
+    pub path: PathBuf,
+    /// The action performed on the file.
+    pub action: TargetAction,
+    /// Whether the change has been written to disk, or is only staged or a dry run.
+    pub applied: bool,
+    /// The results of each `updates` or key path entry, in order. Empty for `Write`, `Copy` and `Changelog`
+    /// actions.
+    pub updates: Vec<UpdateReport>,
+    /// The SHA-256 hash of the original file content, if the file existed.
+    pub old_hash: Option<String>,
+    /// The SHA-256 hash of the new file content.
+    pub new_hash: String,
+    /// A unified diff of the change relative to the script file directory, or the directory that also
+    /// contains the files the script `extends` or `include`s, or `None` if unchanged.
+    pub diff: Option<String>,
+}
+
+impl FileReport {
+    /// Create a report for a file from its original and new content.
+    pub(crate) fn new(
+        path: PathBuf,
+        base_dir: &Path,
+        action: TargetAction,
+        updates: Vec<UpdateReport>,
+        old_content: Option<&[u8]>,
+        new_content: &[u8],
+    ) -> FileReport {
+        let label = path
+            .strip_prefix(base_dir)
+            .unwrap_or(&path)
+            .display()
+            .to_string();
+
+        FileReport {
+            action,
+            applied: false,
+            updates,
+            old_hash: old_content.map(content_hash),
+            new_hash: content_hash(new_content),
+            diff: diff::unified_diff(&label, old_content, new_content),
+            path,
+        }
+    }
+
+    /// Does the new file content differ from the original?
+    pub fn is_changed(&self) -> bool {
+        self.old_hash.as_ref() != Some(&self.new_hash)
+    }
+
+    /// Explain why the file content is out of sync with the script file, or `None` if it is in sync.
+    pub fn out_of_sync_reason(&self) -> Option<String> {
+        if !self.is_changed() {
+            return None;
+        }
+
+        let reason = match self.action {
+            _ if self.old_hash.is_none() => "the file does not exist".to_string(),
+            TargetAction::Write => "the content differs from the 'write' expression".to_string(),
+            TargetAction::Copy => "the content differs from the 'copyFrom' file".to_string(),
+            action => {
+                let diff_lines = || self.diff.as_deref().unwrap_or_default().lines().skip(2);
+                let removed_lines = diff_lines().filter(|line| line.starts_with('-')).count();
+                let added_lines = diff_lines().filter(|line| line.starts_with('+')).count();
+                let changed_lines = removed_lines.max(added_lines);
+
+                format!(
+                    "{} line(s) would be changed by '{}'",
+                    changed_lines,
+                    action.entry_name()
+                )
+            }
+        };
+
+        Some(reason)
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+/// The result of processing a single target.
+pub struct TargetReport {
+    /// The target description.
+    pub description: String,
+    /// The files that were processed.
+    pub files: Vec<FileReport>,
+    /// The files that were skipped because they are outside the filter paths.
+    pub skipped_files: Vec<PathBuf>,
+    /// Why the whole target was skipped, e.g. because its `when` expression is false, or `None` if it was
+    /// processed.
+    pub skip_reason: Option<String>,
+}
+
+#[derive(Clone, Debug, Default, PartialEq)]
+/// The result of processing all the targets in a script file.
+pub struct RunReport {
+    /// The targets, in script order.
+    pub targets: Vec<TargetReport>,
+}
+
+impl RunReport {
+    /// Iterate over every processed file in every target.
+    pub fn files(&self) -> impl Iterator<Item = &FileReport> {
+        self.targets.iter().flat_map(|target| target.files.iter())
+    }
+
+    /// Record that every file has been written, once the transaction that staged them is committed.
+    pub fn mark_applied(&mut self) {
+        for target in self.targets.iter_mut() {
+            for file in target.files.iter_mut() {
+                file.applied = true;
+            }
+        }
+    }
+
+    /// Combine the diffs of every changed file into a single patch.
+    pub fn patch(&self) -> String {
+        self.files()
+            .filter_map(|file| file.diff.as_deref())
+            .collect()
+    }
+}
+
+/// Hash file content for reporting.
+fn content_hash(content: &[u8]) -> String {
+    format!("{:x}", Sha256::digest(content))
+}
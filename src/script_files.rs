@@ -0,0 +1,501 @@
+use crate::{ScriptError, json_node_extra::*, script_error};
+use json5_nodes::{JsonNode, LinkedHashMap};
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+/// The sections that are merged from `extends` and `include` files. The other sections are taken from the
+/// `extends` file if the script does not have them.
+const MERGED_SECTIONS: [&str; 4] = ["vars", "calcVars", "operations", "targets"];
+
+/// How entries from two files that have the same name are combined.
+#[derive(Clone, Copy, PartialEq)]
+enum MergeRule {
+    /// The script overrides the entries of the file it `extends`, and a component overrides the shared
+    /// entries.
+    Override,
+    /// Files that are included must not define the same entries.
+    Conflict,
+}
+
+/// A part of a root node, used to find the file that it came from.
+#[derive(Clone, Copy)]
+pub(crate) enum ScriptPart<'a> {
+    /// The root node itself, which is always from the script file.
+    Root,
+    /// A whole section, e.g. `prerelease`.
+    Section(&'a str),
+    /// An entry in `vars`, `calcVars` or `operations`, by name.
+    Entry(&'a str, &'a str),
+    /// An entry in `targets`, by index.
+    Target(usize),
+}
+
+/// The file that each section, entry and target of a merged root node came from, as an index into the list
+/// of files read, which starts with the script file.
+#[derive(Clone, Debug, Default)]
+struct FileIndexes {
+    sections: LinkedHashMap<String, usize>,
+    entries: LinkedHashMap<(String, String), usize>,
+    targets: Vec<usize>,
+}
+
+impl FileIndexes {
+    /// The indexes of the entries of a single file.
+    fn of_file(entries: &LinkedHashMap<String, JsonNode>, index: usize) -> FileIndexes {
+        let mut indexes = FileIndexes::default();
+
+        for (key, node) in entries.iter() {
+            indexes.sections.insert(key.clone(), index);
+
+            match node {
+                JsonNode::Object(section, _) if MERGED_SECTIONS.contains(&key.as_str()) => {
+                    for (name, _) in section.iter() {
+                        indexes.entries.insert((key.clone(), name.clone()), index);
+                    }
+                }
+                JsonNode::Array(targets, _) if key == "targets" => {
+                    indexes.targets = vec![index; targets.len()];
+                }
+                _ => (),
+            }
+        }
+
+        indexes
+    }
+
+    fn section(&self, key: &str) -> usize {
+        self.sections.get(key).copied().unwrap_or(0)
+    }
+
+    fn entry(&self, key: &str, name: &str) -> usize {
+        self.entries
+            .get(&(key.to_string(), name.to_string()))
+            .copied()
+            .unwrap_or_else(|| self.section(key))
+    }
+
+    fn target(&self, index: usize) -> usize {
+        self.targets
+            .get(index)
+            .copied()
+            .unwrap_or_else(|| self.section("targets"))
+    }
+
+    fn part(&self, part: ScriptPart) -> usize {
+        match part {
+            ScriptPart::Root => 0,
+            ScriptPart::Section(key) => self.section(key),
+            ScriptPart::Entry(key, name) => self.entry(key, name),
+            ScriptPart::Target(index) => self.target(index),
+        }
+    }
+
+    /// Take the indexes of a whole section from the indexes of another root node.
+    fn copy_section(&mut self, key: &str, other: &FileIndexes) {
+        self.sections.insert(key.to_string(), other.section(key));
+        self.entries.retain(|(section, _), _| section != key);
+
+        for ((section, name), index) in other.entries.iter() {
+            if section == key {
+                self.entries.insert((section.clone(), name.clone()), *index);
+            }
+        }
+
+        if key == "targets" {
+            self.targets = other.targets.clone();
+        }
+    }
+}
+
+/// The files that a root node was read from, and the file that each of its sections, `vars`, `calcVars` and
+/// `operations` entries and `targets` came from.
+///
+/// The sources are recorded while the files are merged and are kept alongside the root node, not in it. The
+/// default sources are those of a root node that was not read from a file, which has no files.
+#[derive(Clone, Debug, Default)]
+pub(crate) struct ScriptSources {
+    files: Vec<PathBuf>,
+    indexes: FileIndexes,
+}
+
+impl ScriptSources {
+    /// The script file that the root node was read from.
+    pub(crate) fn script_file(&self) -> Option<&Path> {
+        self.files.first().map(PathBuf::as_path)
+    }
+
+    /// The file that a part of the root node came from.
+    fn file(&self, part: ScriptPart) -> Option<&Path> {
+        self.files
+            .get(self.indexes.part(part))
+            .map(PathBuf::as_path)
+    }
+
+    /// Set the file of an error caused by a part of the root node, if it does not already have one.
+    pub(crate) fn locate(&self, part: ScriptPart, error: ScriptError) -> ScriptError {
+        match self.file(part) {
+            Some(path) => with_file(error, path),
+            None => error,
+        }
+    }
+
+    /// Get the file that defines a var, or `None` if it is the script file.
+    pub(crate) fn var_file(&self, name: &str) -> Option<&Path> {
+        match self.indexes.entry("vars", name) {
+            0 => None,
+            index => self.files.get(index).map(PathBuf::as_path),
+        }
+    }
+
+    /// Get the directory that patch labels are relative to, which is the directory of the script file, or
+    /// the closest directory that also contains every file the script `extends` or `include`s.
+    pub(crate) fn base_dir(&self, script_file: &Path) -> PathBuf {
+        let mut base_dir = script_file.parent().unwrap_or(Path::new(".")).to_path_buf();
+
+        for path in self.files.iter() {
+            while !path.starts_with(&base_dir) && base_dir.pop() {}
+        }
+
+        base_dir
+    }
+
+    /// Get the sources of the root node of a component, which has the entries of the component merged into
+    /// it. The whole component comes from the file that defines the `components` section.
+    pub(crate) fn with_component(
+        &self,
+        entries: &mut LinkedHashMap<String, JsonNode>,
+        component_entries: LinkedHashMap<String, JsonNode>,
+    ) -> Result<ScriptSources, ScriptError> {
+        let mut sources = self.clone();
+        let component_indexes =
+            FileIndexes::of_file(&component_entries, self.indexes.section("components"));
+
+        merge_sources(
+            entries,
+            &mut sources.indexes,
+            component_entries,
+            &component_indexes,
+            MergeRule::Override,
+        )
+        .map_err(|(error, _)| error)?;
+
+        Ok(sources)
+    }
+}
+
+/// Are two nodes the same, including their locations? Unlike `==`, a NaN float is the same as itself.
+pub(crate) fn is_same_node(node: &JsonNode, other: &JsonNode) -> bool {
+    match (node, other) {
+        (JsonNode::Float(value, location), JsonNode::Float(other_value, other_location)) => {
+            value.to_bits() == other_value.to_bits() && location == other_location
+        }
+        (JsonNode::Array(nodes, location), JsonNode::Array(other_nodes, other_location)) => {
+            location == other_location
+                && nodes.len() == other_nodes.len()
+                && nodes
+                    .iter()
+                    .zip(other_nodes.iter())
+                    .all(|(node, other)| is_same_node(node, other))
+        }
+        (JsonNode::Object(entries, location), JsonNode::Object(other_entries, other_location)) => {
+            location == other_location
+                && entries.len() == other_entries.len()
+                && entries.iter().zip(other_entries.iter()).all(
+                    |((key, node), (other_key, other))| {
+                        key == other_key && is_same_node(node, other)
+                    },
+                )
+        }
+        _ => node == other,
+    }
+}
+
+/// Read a script file and the files it `extends` and `include`s, merging them into a single root node.
+///
+/// Returns the content of the script file, the merged root node, the canonical path of the script file, and
+/// the sources recording the file that each section, entry and target of the root node came from.
+pub(crate) fn load(
+    script_file: &Path,
+) -> anyhow::Result<(String, JsonNode, PathBuf, ScriptSources)> {
+    let script_file = script_file.canonicalize()?;
+    let content = fs::read_to_string(&script_file)?;
+    let root_node = json5_nodes::parse(&content)?;
+    let mut files = vec![script_file.clone()];
+    let (root_node, indexes) = resolve(root_node, &mut files, &mut vec![0])
+        .map_err(|error| with_file(error, &script_file))?;
+
+    Ok((
+        content,
+        root_node,
+        script_file,
+        ScriptSources { files, indexes },
+    ))
+}
+
+/// Set the script file of an error that does not have one.
+fn with_file(error: ScriptError, path: &Path) -> ScriptError {
+    match error.script_file {
+        Some(_) => error,
+        None => ScriptError::new(error.message, Some(path.to_path_buf()), error.location),
+    }
+}
+
+/// Read a file that is extended or included by the file at `stack`'s last index, returning its merged root
+/// and sources.
+fn load_file(
+    path: PathBuf,
+    node: &JsonNode,
+    files: &mut Vec<PathBuf>,
+    stack: &mut Vec<usize>,
+) -> Result<(JsonNode, FileIndexes), ScriptError> {
+    let path = path.canonicalize().map_err(|e| {
+        script_error!(
+            format!("Unable to read script file '{}' - {}", path.display(), e),
+            node
+        )
+    })?;
+
+    if stack.iter().any(|index| files[*index] == path) {
+        return Err(script_error!(
+            format!("Script file '{}' includes itself", path.display()),
+            node
+        ));
+    }
+
+    let content = fs::read_to_string(&path).map_err(|e| {
+        script_error!(
+            format!("Unable to read script file '{}' - {}", path.display(), e),
+            node
+        )
+    })?;
+    let root_node =
+        json5_nodes::parse(&content).map_err(|e| with_file(ScriptError::from(e), &path))?;
+    let index = files.len();
+
+    files.push(path.clone());
+    stack.push(index);
+
+    let result = resolve(root_node, files, stack).map_err(|error| with_file(error, &path));
+
+    stack.pop();
+
+    result
+}
+
+/// Replace the `extends` and `include` entries of a root node with the merged content of those files,
+/// returning the merged root node and the file that each part of it came from.
+fn resolve(
+    root_node: JsonNode,
+    files: &mut Vec<PathBuf>,
+    stack: &mut Vec<usize>,
+) -> Result<(JsonNode, FileIndexes), ScriptError> {
+    let index = *stack.last().unwrap();
+    let JsonNode::Object(mut entries, location) = root_node else {
+        return Ok((root_node, FileIndexes::default()));
+    };
+    let extends_node = entries.remove("extends");
+    let include_node = entries.remove("include");
+    let own_sources = FileIndexes::of_file(&entries, index);
+
+    if extends_node.is_none() && include_node.is_none() {
+        return Ok((JsonNode::Object(entries, location), own_sources));
+    }
+
+    let dir = files[index]
+        .parent()
+        .unwrap_or(Path::new("."))
+        .to_path_buf();
+    let mut merged_entries = LinkedHashMap::new();
+    let mut sources = FileIndexes::default();
+
+    if let Some(extends_node) = &extends_node {
+        if !extends_node.is_string() {
+            return Err(script_error!("'extends' must be a string", extends_node));
+        }
+
+        let (base_node, base_sources) = load_file(
+            dir.join(extends_node.get_string()),
+            extends_node,
+            files,
+            stack,
+        )?;
+
+        if let JsonNode::Object(base_entries, _) = base_node {
+            merged_entries = base_entries;
+            sources = base_sources;
+        }
+    }
+
+    if let Some(include_node) = &include_node {
+        for file_node in include_node.get_array_iter()? {
+            if !file_node.is_string() {
+                return Err(script_error!("'include' entry must be a string", file_node));
+            }
+
+            let (included_node, included_sources) =
+                load_file(dir.join(file_node.get_string()), file_node, files, stack)?;
+
+            if let JsonNode::Object(included_entries, _) = included_node {
+                for (key, node) in included_entries.iter() {
+                    if !MERGED_SECTIONS.contains(&key.as_str()) {
+                        let error = script_error!(
+                            format!(
+                                "Included files can only contain {}, not '{}'",
+                                MERGED_SECTIONS
+                                    .iter()
+                                    .map(|section| format!("'{}'", section))
+                                    .collect::<Vec<_>>()
+                                    .join(", "),
+                                key
+                            ),
+                            node
+                        );
+
+                        return Err(with_file(error, &files[included_sources.section(key)]));
+                    }
+                }
+
+                merge_sources(
+                    &mut merged_entries,
+                    &mut sources,
+                    included_entries,
+                    &included_sources,
+                    MergeRule::Conflict,
+                )
+                .map_err(|(error, index)| with_file(error, &files[index]))?;
+            }
+        }
+    }
+
+    merge_sources(
+        &mut merged_entries,
+        &mut sources,
+        entries,
+        &own_sources,
+        MergeRule::Override,
+    )
+    .map_err(|(error, index)| with_file(error, &files[index]))?;
+
+    Ok((JsonNode::Object(merged_entries, location), sources))
+}
+
+/// Merge the sections of one root node into another, recording the file each merged part came from.
+///
+/// Entries in `vars`, `calcVars` and `operations` are combined by name, and `targets` are appended. With
+/// [`MergeRule::Override`] an entry replaces an entry with the same name, a target replaces a target with
+/// the same `description`, and other sections replace the whole section. With [`MergeRule::Conflict`] it
+/// is an error for an entry to be defined twice, and the error is returned with the index of the file that
+/// defines it again.
+fn merge_sources(
+    entries: &mut LinkedHashMap<String, JsonNode>,
+    sources: &mut FileIndexes,
+    source_entries: LinkedHashMap<String, JsonNode>,
+    source_sources: &FileIndexes,
+    rule: MergeRule,
+) -> Result<(), (ScriptError, usize)> {
+    for (key, source_node) in source_entries {
+        let Some(node) = entries.get_mut(&key) else {
+            sources.copy_section(&key, source_sources);
+            entries.insert(key, source_node);
+            continue;
+        };
+
+        match (node, source_node) {
+            (
+                JsonNode::Object(section, location),
+                JsonNode::Object(source_section, source_location),
+            ) if MERGED_SECTIONS.contains(&key.as_str()) => {
+                for (name, entry_node) in source_section {
+                    let index = source_sources.entry(&key, &name);
+
+                    if rule == MergeRule::Conflict && section.contains_key(&name) {
+                        return Err((
+                            script_error!(
+                                format!(
+                                    "'{}' entry '{}' is defined in more than one file",
+                                    key, name
+                                ),
+                                entry_node
+                            ),
+                            index,
+                        ));
+                    }
+
+                    sources.entries.insert((key.clone(), name.clone()), index);
+
+                    match section.get_mut(&name) {
+                        Some(existing_node) => *existing_node = entry_node,
+                        None => {
+                            section.insert(name, entry_node);
+                        }
+                    }
+                }
+
+                if rule == MergeRule::Override {
+                    *location = source_location;
+                    sources
+                        .sections
+                        .insert(key.clone(), source_sources.section(&key));
+                }
+            }
+            (
+                JsonNode::Array(targets, location),
+                JsonNode::Array(source_targets, source_location),
+            ) if key == "targets" => {
+                sources.targets.resize(targets.len(), sources.section(&key));
+
+                for (source_index, target_node) in source_targets.into_iter().enumerate() {
+                    let index = source_sources.target(source_index);
+                    let description = target_node
+                        .get_object_entry("description")
+                        .ok()
+                        .map(|node| node.get_string());
+                    let existing_position = targets.iter().position(|node| {
+                        description.is_some()
+                            && node
+                                .get_object_entry("description")
+                                .ok()
+                                .map(|node| node.get_string())
+                                == description
+                    });
+
+                    match existing_position {
+                        Some(position) if rule == MergeRule::Override => {
+                            targets[position] = target_node;
+                            sources.targets[position] = index;
+                        }
+                        _ => {
+                            targets.push(target_node);
+                            sources.targets.push(index);
+                        }
+                    }
+                }
+
+                if rule == MergeRule::Override {
+                    *location = source_location;
+                    sources
+                        .sections
+                        .insert(key.clone(), source_sources.section(&key));
+                }
+            }
+            (node, source_node) => {
+                if rule == MergeRule::Conflict {
+                    return Err((
+                        script_error!(
+                            format!("'{}' is defined in more than one file", key),
+                            source_node
+                        ),
+                        source_sources.section(&key),
+                    ));
+                }
+
+                *node = source_node;
+                sources.copy_section(&key, source_sources);
+            }
+        }
+    }
+
+    Ok(())
+}
@@ -0,0 +1,183 @@
+use evalexpr::{
+    ContextWithMutableFunctions, EvalexprError, EvalexprResult, Function, HashMapContext, Value,
+};
+use semver::{BuildMetadata, Prerelease, Version, VersionReq};
+use std::cmp::Ordering;
+
+/// Add the `semver::` functions to an expression context.
+pub(crate) fn add_functions(context: &mut HashMapContext) -> EvalexprResult<()> {
+    context.set_function(
+        "semver::format".to_owned(),
+        Function::new(|arg| Ok(Value::from(to_version(arg)?.to_string()))),
+    )?;
+    context.set_function(
+        "semver::parse".to_owned(),
+        Function::new(|arg| {
+            let version = parse_version(&arg.as_string()?)?;
+
+            Ok(Value::Tuple(vec![
+                Value::Int(to_int(version.major)?),
+                Value::Int(to_int(version.minor)?),
+                Value::Int(to_int(version.patch)?),
+                Value::from(version.pre.as_str()),
+                Value::from(version.build.as_str()),
+            ]))
+        }),
+    )?;
+    context.set_function(
+        "semver::bump".to_owned(),
+        Function::new(|arg| {
+            let [version, part] = arg.as_fixed_len_tuple(2)?.try_into().unwrap();
+
+            Ok(Value::from(
+                bump(to_version(&version)?, &part.as_string()?)?.to_string(),
+            ))
+        }),
+    )?;
+    context.set_function(
+        "semver::compare".to_owned(),
+        Function::new(|arg| {
+            let [a, b] = arg.as_fixed_len_tuple(2)?.try_into().unwrap();
+
+            Ok(Value::Int(
+                match to_version(&a)?.cmp_precedence(&to_version(&b)?) {
+                    Ordering::Less => -1,
+                    Ordering::Equal => 0,
+                    Ordering::Greater => 1,
+                },
+            ))
+        }),
+    )?;
+    context.set_function(
+        "semver::satisfies".to_owned(),
+        Function::new(|arg| {
+            let [version, req] = arg.as_fixed_len_tuple(2)?.try_into().unwrap();
+            let req_string = req.as_string()?;
+            let version_req = VersionReq::parse(&req_string).map_err(|e| {
+                EvalexprError::CustomMessage(format!(
+                    "'{}' is not a valid version requirement - {}",
+                    req_string, e
+                ))
+            })?;
+
+            Ok(Value::Boolean(version_req.matches(&to_version(&version)?)))
+        }),
+    )?;
+
+    Ok(())
+}
+
+/// Convert a version string, or a `(major, minor, patch, prerelease, build)` tuple where the last two
+/// entries are optional, into a version.
+fn to_version(value: &Value) -> EvalexprResult<Version> {
+    let Value::Tuple(tuple) = value else {
+        return parse_version(&value.as_string()?);
+    };
+
+    if !(3..=5).contains(&tuple.len()) {
+        return Err(EvalexprError::CustomMessage(format!(
+            "Expected a version string or a tuple of 3 to 5 values (major, minor, patch, prerelease, build), got {}",
+            value
+        )));
+    }
+
+    let mut version = Version::new(to_u64(&tuple[0])?, to_u64(&tuple[1])?, to_u64(&tuple[2])?);
+
+    if let Some(pre) = tuple.get(3) {
+        let pre = pre.as_string()?;
+
+        version.pre = Prerelease::new(&pre).map_err(|e| {
+            EvalexprError::CustomMessage(format!("'{}' is not a valid prerelease - {}", pre, e))
+        })?;
+    }
+
+    if let Some(build) = tuple.get(4) {
+        let build = build.as_string()?;
+
+        version.build = BuildMetadata::new(&build).map_err(|e| {
+            EvalexprError::CustomMessage(format!("'{}' is not valid build metadata - {}", build, e))
+        })?;
+    }
+
+    Ok(version)
+}
+
+fn parse_version(s: &str) -> EvalexprResult<Version> {
+    Version::parse(s).map_err(|e| {
+        EvalexprError::CustomMessage(format!("'{}' is not a valid semantic version - {}", s, e))
+    })
+}
+
+fn to_u64(value: &Value) -> EvalexprResult<u64> {
+    let n = value.as_int()?;
+
+    u64::try_from(n).map_err(|_| {
+        EvalexprError::CustomMessage(format!("Version number {} must not be negative", n))
+    })
+}
+
+fn to_int(n: u64) -> EvalexprResult<i64> {
+    i64::try_from(n)
+        .map_err(|_| EvalexprError::CustomMessage(format!("Version number {} is too large", n)))
+}
+
+/// Bump the `major`, `minor`, `patch` or `prerelease` part of a version.
+///
+/// Bumping a prerelease version to the release it precedes gives that release, so `1.3.0-rc.1` bumped by
+/// `minor` is `1.3.0`. Bumping `prerelease` increments the last numeric identifier, appending `.0` if there
+/// is none, or starts a `0` prerelease of the next patch version.
+fn bump(mut version: Version, part: &str) -> EvalexprResult<Version> {
+    let is_prerelease = !version.pre.is_empty();
+
+    version.build = BuildMetadata::EMPTY;
+
+    match part {
+        "major" => {
+            if !is_prerelease || version.minor != 0 || version.patch != 0 {
+                version.major += 1;
+            }
+
+            version.minor = 0;
+            version.patch = 0;
+            version.pre = Prerelease::EMPTY;
+        }
+        "minor" => {
+            if !is_prerelease || version.patch != 0 {
+                version.minor += 1;
+            }
+
+            version.patch = 0;
+            version.pre = Prerelease::EMPTY;
+        }
+        "patch" => {
+            if !is_prerelease {
+                version.patch += 1;
+            }
+
+            version.pre = Prerelease::EMPTY;
+        }
+        "prerelease" if is_prerelease => {
+            let mut identifiers: Vec<String> =
+                version.pre.split('.').map(|s| s.to_string()).collect();
+
+            match identifiers.last().and_then(|s| s.parse::<u64>().ok()) {
+                Some(n) => *identifiers.last_mut().unwrap() = (n + 1).to_string(),
+                None => identifiers.push("0".to_string()),
+            }
+
+            version.pre = Prerelease::new(&identifiers.join(".")).unwrap();
+        }
+        "prerelease" => {
+            version.patch += 1;
+            version.pre = Prerelease::new("0").unwrap();
+        }
+        _ => {
+            return Err(EvalexprError::CustomMessage(format!(
+                "Unknown version part '{}', expected 'major', 'minor', 'patch' or 'prerelease'",
+                part
+            )));
+        }
+    }
+
+    Ok(version)
+}
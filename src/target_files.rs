@@ -0,0 +1,162 @@
+use crate::{ScriptError, json_node_extra::*, script_error};
+use globset::{Glob, GlobBuilder, GlobMatcher, GlobSet, GlobSetBuilder};
+use ignore::WalkBuilder;
+use json5_nodes::JsonNode;
+use std::path::{Path, PathBuf};
+
+/// A single entry in a target's `files` array.
+enum FileEntry<'a> {
+    Path(PathBuf, &'a JsonNode),
+    Glob(GlobMatcher, &'a JsonNode),
+}
+
+/// Does a `files` entry contain glob pattern characters?
+fn is_glob(s: &str) -> bool {
+    s.contains(['*', '?', '[', '{'])
+}
+
+/// Build a glob where `*` does not match path separators, as in `.gitignore` files.
+fn build_glob(pattern: &str, node: &JsonNode) -> Result<Glob, ScriptError> {
+    GlobBuilder::new(pattern)
+        .literal_separator(true)
+        .build()
+        .map_err(|e| script_error!(format!("Glob pattern is not valid - {}", e), node))
+}
+
+/// Expand the entries of a target's `files` array into a list of files, relative to the base directory.
+///
+/// Entries are either literal paths, directories (which include all the files beneath them), glob patterns
+/// or glob patterns prefixed with `!` which exclude matching files. Each file is returned with the node of
+/// the entry that produced it.
+pub(crate) fn expand_target_files<'a>(
+    files_node: &'a JsonNode,
+    base_dir: &Path,
+    respect_gitignore: bool,
+) -> Result<Vec<(PathBuf, &'a JsonNode)>, ScriptError> {
+    let mut entries: Vec<FileEntry> = vec![];
+    let mut exclude_builder = GlobSetBuilder::new();
+
+    for file_node in files_node.get_array_iter()? {
+        let file_str = file_node.get_string();
+
+        if Path::new(file_str.trim_start_matches('!')).is_absolute() {
+            return Err(script_error!(
+                format!(
+                    "Target file '{}' is an absolute path, but should be relative to the script file",
+                    file_str
+                ),
+                file_node
+            ));
+        }
+
+        if let Some(pattern) = file_str.strip_prefix('!') {
+            exclude_builder.add(build_glob(pattern, file_node)?);
+        } else if is_glob(&file_str) {
+            entries.push(FileEntry::Glob(
+                build_glob(&file_str, file_node)?.compile_matcher(),
+                file_node,
+            ));
+        } else {
+            let path = path_clean::clean(base_dir.join(&file_str));
+
+            if !file_str.ends_with('/') && !path.is_dir() {
+                entries.push(FileEntry::Path(path, file_node));
+                continue;
+            }
+
+            let relative_path = path.strip_prefix(base_dir).map_err(|_| {
+                script_error!(
+                    format!(
+                        "Target directory '{}' is outside the script file directory '{}', list its files instead",
+                        file_str,
+                        base_dir.display()
+                    ),
+                    file_node
+                )
+            })?;
+            let pattern = relative_path.join("**").display().to_string();
+
+            entries.push(FileEntry::Glob(
+                build_glob(&pattern, file_node)?.compile_matcher(),
+                file_node,
+            ));
+        }
+    }
+
+    let excludes: GlobSet = exclude_builder
+        .build()
+        .map_err(|e| script_error!(format!("Glob pattern is not valid - {}", e), files_node))?;
+    let walked_files = if entries.iter().any(|e| matches!(e, FileEntry::Glob(..))) {
+        walk_files(base_dir, respect_gitignore)
+    } else {
+        vec![]
+    };
+    let mut target_files: Vec<(PathBuf, &JsonNode)> = vec![];
+
+    for entry in entries {
+        let (paths, file_node) = match entry {
+            FileEntry::Glob(glob, file_node) => {
+                let matched_paths: Vec<PathBuf> = walked_files
+                    .iter()
+                    .filter(|(relative_path, _)| glob.is_match(relative_path))
+                    .map(|(_, path)| path.clone())
+                    .collect();
+
+                if matched_paths.is_empty() {
+                    return Err(script_error!(
+                        format!("'{}' did not match any files", file_node.get_string()),
+                        file_node
+                    ));
+                }
+
+                log::info!(
+                    "Expanded '{}' to {} file(s)",
+                    file_node.get_string(),
+                    matched_paths.len()
+                );
+
+                (matched_paths, file_node)
+            }
+            FileEntry::Path(path, file_node) => (vec![path], file_node),
+        };
+
+        for path in paths {
+            let is_excluded = path
+                .strip_prefix(base_dir)
+                .is_ok_and(|relative_path| excludes.is_match(relative_path));
+
+            if !is_excluded && !target_files.iter().any(|(p, _)| *p == path) {
+                target_files.push((path, file_node));
+            }
+        }
+    }
+
+    Ok(target_files)
+}
+
+/// Find all the files under a directory, returning their relative and absolute paths in sorted order.
+fn walk_files(base_dir: &Path, respect_gitignore: bool) -> Vec<(PathBuf, PathBuf)> {
+    let mut files: Vec<(PathBuf, PathBuf)> = WalkBuilder::new(base_dir)
+        .hidden(false)
+        .ignore(false)
+        .parents(respect_gitignore)
+        .git_ignore(respect_gitignore)
+        .git_exclude(respect_gitignore)
+        .git_global(respect_gitignore)
+        .require_git(false)
+        .filter_entry(|entry| entry.file_name() != ".git")
+        .build()
+        .filter_map(|entry| entry.ok())
+        .filter(|entry| entry.file_type().is_some_and(|t| t.is_file()))
+        .filter_map(|entry| {
+            let path = entry.into_path();
+
+            path.strip_prefix(base_dir)
+                .ok()
+                .map(|relative_path| (relative_path.to_path_buf(), path.clone()))
+        })
+        .collect();
+
+    files.sort();
+    files
+}
@@ -0,0 +1,130 @@
+use crate::{
+    ScriptError, UpdateReport,
+    json_node_extra::*,
+    key_path::{self, PathSegment},
+    script_error,
+};
+use evalexpr::{HashMapContext, Value};
+use json5_nodes::JsonNode;
+use std::path::Path;
+use toml_edit::{DocumentMut, Item, Table};
+
+/// Apply the `toml` entries of a target to the content of a TOML file.
+///
+/// Each entry sets the value at a dotted key path to the result of an expression, keeping the comments,
+/// whitespace and formatting of the rest of the file.
+pub(crate) fn update_toml(
+    target_file: &Path,
+    content: &str,
+    toml_node: &JsonNode,
+    context: &HashMapContext,
+    update_reports: &mut Vec<UpdateReport>,
+) -> Result<String, ScriptError> {
+    let mut document: DocumentMut = content.parse().map_err(|e| {
+        script_error!(
+            format!("File '{}' is not valid TOML - {}", target_file.display(), e),
+            toml_node
+        )
+    })?;
+
+    for entry_node in toml_node.get_array_iter()? {
+        let path_node = entry_node.get_object_entry("path")?;
+        let path = path_node.get_string();
+        let segments =
+            key_path::parse_dotted(&path).map_err(|message| script_error!(message, path_node))?;
+        let value_node = entry_node.get_object_entry("value")?;
+        let value = evalexpr::eval_with_context(&value_node.get_string(), context)
+            .map_err(|e| script_error!(e.to_string(), value_node))?;
+        let toml_value = match value {
+            Value::String(s) => toml_edit::Value::from(s),
+            Value::Int(n) => toml_edit::Value::from(n),
+            Value::Float(f) => toml_edit::Value::from(f),
+            Value::Boolean(b) => toml_edit::Value::from(b),
+            _ => {
+                return Err(script_error!(
+                    "'value' must evaluate to a string, integer, float or boolean",
+                    value_node
+                ));
+            }
+        };
+        let existing_value =
+            table_value_mut(document.as_table_mut(), &segments).ok_or_else(|| {
+                script_error!(
+                    format!(
+                        "Key path '{}' not found in '{}'",
+                        path,
+                        target_file.display()
+                    ),
+                    path_node
+                )
+            })?;
+
+        if existing_value.is_array() || existing_value.is_inline_table() {
+            return Err(script_error!(
+                format!(
+                    "Key path '{}' in '{}' is {}, not a single value",
+                    path,
+                    target_file.display(),
+                    existing_value.type_name()
+                ),
+                path_node
+            ));
+        }
+
+        let decor = existing_value.decor().clone();
+
+        *existing_value = toml_value;
+        *existing_value.decor_mut() = decor;
+
+        update_reports.push(UpdateReport {
+            search: path,
+            match_count: 1,
+            skip_reason: None,
+        });
+    }
+
+    Ok(document.to_string())
+}
+
+fn table_value_mut<'a>(
+    table: &'a mut Table,
+    segments: &[PathSegment],
+) -> Option<&'a mut toml_edit::Value> {
+    match segments.split_first() {
+        Some((PathSegment::Key(key), rest)) => item_value_mut(table.get_mut(key)?, rest),
+        _ => None,
+    }
+}
+
+fn item_value_mut<'a>(
+    item: &'a mut Item,
+    segments: &[PathSegment],
+) -> Option<&'a mut toml_edit::Value> {
+    match item {
+        Item::Value(value) => value_mut(value, segments),
+        Item::Table(table) => table_value_mut(table, segments),
+        Item::ArrayOfTables(array) => match segments.split_first() {
+            Some((PathSegment::Index(index), rest)) => {
+                table_value_mut(array.get_mut(*index)?, rest)
+            }
+            _ => None,
+        },
+        Item::None => None,
+    }
+}
+
+fn value_mut<'a>(
+    value: &'a mut toml_edit::Value,
+    segments: &[PathSegment],
+) -> Option<&'a mut toml_edit::Value> {
+    match (value, segments.split_first()) {
+        (value, None) => Some(value),
+        (toml_edit::Value::InlineTable(table), Some((PathSegment::Key(key), rest))) => {
+            value_mut(table.get_mut(key)?, rest)
+        }
+        (toml_edit::Value::Array(array), Some((PathSegment::Index(index), rest))) => {
+            value_mut(array.get_mut(*index)?, rest)
+        }
+        _ => None,
+    }
+}
@@ -0,0 +1,173 @@
+use crate::{ScriptError, script_error};
+use std::{
+    fs, io,
+    path::{Path, PathBuf},
+};
+
+#[derive(Clone, Debug)]
+struct StagedFile {
+    path: PathBuf,
+    original_content: Option<Vec<u8>>,
+    content: Vec<u8>,
+}
+
+impl StagedFile {
+    /// Does the staged content differ from the original, or is the file new?
+    fn is_changed(&self) -> bool {
+        self.original_content.as_ref() != Some(&self.content)
+    }
+}
+
+#[derive(Clone, Debug, Default)]
+/// A set of file changes that are written to disk all together, or not at all.
+pub struct Transaction {
+    staged_files: Vec<StagedFile>,
+}
+
+impl Transaction {
+    /// Create a new, empty transaction.
+    pub fn new() -> Transaction {
+        Transaction::default()
+    }
+
+    /// Stage new content for a file, replacing any content already staged for it.
+    pub(crate) fn stage(&mut self, path: &Path, content: Vec<u8>) {
+        if let Some(staged_file) = self.staged_files.iter_mut().find(|f| f.path == path) {
+            staged_file.content = content;
+        } else {
+            self.staged_files.push(StagedFile {
+                path: path.to_path_buf(),
+                original_content: fs::read(path).ok(),
+                content,
+            });
+        }
+    }
+
+    /// Read the content of a file, as it will be once the transaction is committed.
+    pub(crate) fn read(&self, path: &Path) -> io::Result<Vec<u8>> {
+        match self.staged_files.iter().find(|f| f.path == path) {
+            Some(staged_file) => Ok(staged_file.content.clone()),
+            None => fs::read(path),
+        }
+    }
+
+    /// The paths of all the staged files, in the order they were staged.
+    pub fn paths(&self) -> impl Iterator<Item = &Path> {
+        self.staged_files.iter().map(|f| f.path.as_path())
+    }
+
+    /// The paths of the staged files whose content differs from the original, in the order they were staged.
+    pub fn changed_paths(&self) -> impl Iterator<Item = &Path> {
+        self.staged_files
+            .iter()
+            .filter(|f| f.is_changed())
+            .map(|f| f.path.as_path())
+    }
+
+    /// Write all the changed staged files to disk, leaving the files whose content is unchanged untouched.
+    ///
+    /// Each file is first written to a temporary file in the same directory and only once all of
+    /// them have been written successfully are they renamed into place. If any step fails the
+    /// temporary files are removed and any files already renamed are restored to their original
+    /// content.
+    pub fn commit(self) -> Result<(), ScriptError> {
+        let staged_files: Vec<&StagedFile> = self
+            .staged_files
+            .iter()
+            .filter(|f| f.is_changed())
+            .collect();
+        let mut temp_paths: Vec<PathBuf> = vec![];
+
+        for staged_file in staged_files.iter() {
+            let temp_path = Self::temp_path(&staged_file.path);
+
+            if let Err(err) =
+                Self::write_temp_file(&temp_path, &staged_file.path, &staged_file.content)
+            {
+                temp_paths.push(temp_path);
+                Self::remove_files(&temp_paths);
+                return Err(script_error!(format!(
+                    "Unable to write to file '{}' - {}",
+                    staged_file.path.display(),
+                    err
+                )));
+            }
+
+            temp_paths.push(temp_path);
+        }
+
+        for (index, (staged_file, temp_path)) in
+            staged_files.iter().zip(temp_paths.iter()).enumerate()
+        {
+            if let Err(err) = fs::rename(temp_path, &staged_file.path) {
+                Self::remove_files(&temp_paths[index..]);
+                Self::rollback(&staged_files[..index]);
+                return Err(script_error!(format!(
+                    "Unable to replace file '{}' - {}; all changes have been rolled back",
+                    staged_file.path.display(),
+                    err
+                )));
+            }
+        }
+
+        Ok(())
+    }
+
+    fn temp_path(path: &Path) -> PathBuf {
+        let file_name = path
+            .file_name()
+            .map(|s| s.to_string_lossy().into_owned())
+            .unwrap_or_default();
+
+        path.with_file_name(format!(".{}.stampver-tmp", file_name))
+    }
+
+    fn write_temp_file(temp_path: &Path, path: &Path, content: &[u8]) -> io::Result<()> {
+        fs::write(temp_path, content)?;
+
+        // Keep the permissions of the file being replaced
+        if let Ok(metadata) = fs::metadata(path) {
+            fs::set_permissions(temp_path, metadata.permissions())?;
+        }
+
+        Ok(())
+    }
+
+    /// Replace the content of a file through a temporary file, so the file is never left partly written.
+    fn replace_file(path: &Path, content: &[u8]) -> io::Result<()> {
+        let temp_path = Self::temp_path(path);
+        let result = Self::write_temp_file(&temp_path, path, content)
+            .and_then(|_| fs::rename(&temp_path, path));
+
+        if result.is_err() {
+            let _ = fs::remove_file(&temp_path);
+        }
+
+        result
+    }
+
+    fn remove_files(paths: &[PathBuf]) {
+        for path in paths {
+            let _ = fs::remove_file(path);
+        }
+    }
+
+    /// Restore staged files that have been renamed into place to their original state, replacing them through
+    /// temporary files in the same way as they were written.
+    fn rollback(staged_files: &[&StagedFile]) {
+        for staged_file in staged_files.iter().rev() {
+            let result = match &staged_file.original_content {
+                Some(content) => Self::replace_file(&staged_file.path, content),
+                None => fs::remove_file(&staged_file.path),
+            };
+
+            if let Err(err) = result {
+                log::error!(
+                    "Unable to restore file '{}' - {}",
+                    staged_file.path.display(),
+                    err
+                );
+            }
+        }
+    }
+}
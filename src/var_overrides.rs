@@ -0,0 +1,158 @@
+use crate::{ScriptError, json_node_extra::*, script_error, script_files::ScriptPart};
+use evalexpr::{ContextWithMutableVariables, HashMapContext, Value};
+use json5_nodes::JsonNode;
+
+/// The value of a var override.
+#[derive(Clone)]
+pub(crate) enum Override {
+    /// A value that is used as given.
+    Value(Value),
+    /// Text that is parsed for each root node, so that it has the type of the var in that root node.
+    Text(String),
+}
+
+/// Parse the text of a var override, e.g. from `--set name=value`.
+///
+/// An override of a `vars` entry is converted to the type of the entry. An override of a `calcVars` entry
+/// is an integer, float or boolean if the text is one, otherwise a string.
+pub(crate) fn parse(root_node: &JsonNode, name: &str, text: &str) -> Result<Value, ScriptError> {
+    let vars_node = root_node.get_object_entry("vars")?;
+
+    let Ok(var_node) = vars_node.get_object_entry(name) else {
+        find_calc_var(root_node, name)?;
+
+        return Ok(text
+            .parse()
+            .map(Value::Int)
+            .or_else(|_| text.parse().map(Value::Float))
+            .or_else(|_| text.parse().map(Value::Boolean))
+            .unwrap_or_else(|_| Value::from(text)));
+    };
+    let value = match var_node.get_value() {
+        Value::Int(_) => text.parse().ok().map(Value::Int),
+        Value::Float(_) => text.parse().ok().map(Value::Float),
+        Value::Boolean(_) => text.parse().ok().map(Value::Boolean),
+        _ => Some(Value::from(text)),
+    };
+
+    value.ok_or_else(|| {
+        script_error!(
+            format!(
+                "Value '{}' for var '{}' must be {}",
+                text,
+                name,
+                type_name(&var_node.get_value())
+            ),
+            var_node
+        )
+    })
+}
+
+/// Set the override of a var in a context if the root node defines the var, checking that an override of a
+/// `vars` entry keeps its type. An override of a var that the root node does not define is ignored, so that
+/// it can apply to only some of the components of a script.
+pub(crate) fn apply(
+    root_node: &JsonNode,
+    name: &str,
+    var_override: &Override,
+    context: &mut HashMapContext,
+) -> Result<(), ScriptError> {
+    if !is_defined(root_node, name) {
+        return Ok(());
+    }
+
+    let value = match var_override {
+        Override::Value(value) => value.clone(),
+        Override::Text(text) => parse(root_node, name, text)?,
+    };
+
+    if let Ok(var_node) = root_node.get_object_entry("vars")?.get_object_entry(name) {
+        let var_value = var_node.get_value();
+
+        if std::mem::discriminant(&var_value) != std::mem::discriminant(&value) {
+            return Err(script_error!(
+                format!(
+                    "Override for var '{}' must be {}",
+                    name,
+                    type_name(&var_value)
+                ),
+                var_node
+            ));
+        }
+    }
+
+    log::info!("Overriding var '{}' with {}", name, value);
+    context.set_value(name.to_string(), value)?;
+
+    Ok(())
+}
+
+/// The part of a root node that an error about the override of a var is caused by, which is the `vars`
+/// entry for the var if there is one, otherwise the `calcVars` section.
+pub(crate) fn script_part<'a>(root_node: &JsonNode, name: &'a str) -> ScriptPart<'a> {
+    match root_node
+        .get_object_entry("vars")
+        .and_then(|vars_node| vars_node.get_object_entry(name))
+    {
+        Ok(_) => ScriptPart::Entry("vars", name),
+        Err(_) => ScriptPart::Section("calcVars"),
+    }
+}
+
+/// Check that each override names a `vars` or `calcVars` entry in at least one of the root nodes.
+pub(crate) fn check(
+    root_nodes: &[&JsonNode],
+    overrides: &[(String, Override)],
+) -> Result<(), ScriptError> {
+    for (name, _) in overrides.iter() {
+        if root_nodes
+            .iter()
+            .any(|root_node| is_defined(root_node, name))
+        {
+            continue;
+        }
+
+        if let Some(root_node) = root_nodes.first() {
+            find_calc_var(root_node, name)?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Is a var in the `vars` or `calcVars` of a root node?
+fn is_defined(root_node: &JsonNode, name: &str) -> bool {
+    ["vars", "calcVars"].iter().any(|section| {
+        root_node
+            .get_object_entry(section)
+            .is_ok_and(|node| node.get_object_entry(name).is_ok())
+    })
+}
+
+/// Check that a var that is not in `vars` is in `calcVars`.
+fn find_calc_var(root_node: &JsonNode, name: &str) -> Result<(), ScriptError> {
+    let calc_vars_node = root_node.get_object_entry("calcVars")?;
+
+    calc_vars_node
+        .get_object_entry(name)
+        .map(|_| ())
+        .map_err(|_| {
+            script_error!(
+                format!(
+                    "Cannot override var '{}' because it is not in 'vars' or 'calcVars'",
+                    name
+                ),
+                calc_vars_node
+            )
+        })
+}
+
+/// The name of the type of a value, for error messages.
+fn type_name(value: &Value) -> &'static str {
+    match value {
+        Value::Int(_) => "an integer",
+        Value::Float(_) => "a float",
+        Value::Boolean(_) => "a boolean",
+        _ => "a string",
+    }
+}
@@ -0,0 +1,451 @@
+use crate::{ScriptError, UpdateReport, json_edit, json_node_extra::*, script_error};
+use evalexpr::{HashMapContext, Value};
+use json5_nodes::JsonNode;
+use std::{ops::Range, path::Path};
+use xmlparser::{ElementEnd, Token, Tokenizer};
+
+/// An XML element with just enough information to find and replace its text or attribute values.
+struct XmlElement {
+    /// The qualified name as written in the document, e.g. `android:versionCode`.
+    name: String,
+    /// Attribute names and the byte ranges of their values, not including the quotes.
+    attributes: Vec<(String, Range<usize>)>,
+    /// The byte range of the element content, or the range of the `/>` for an empty element tag.
+    content: Range<usize>,
+    is_empty_tag: bool,
+    /// The text and CDATA sections of the content, in order, not including those of child elements.
+    text_nodes: Vec<XmlTextNode>,
+    /// The unescaped text of the element, ignoring child elements.
+    text: String,
+    children: Vec<XmlElement>,
+}
+
+/// A text or CDATA section within the content of an element.
+struct XmlTextNode {
+    /// The byte range of the text, or of the whole CDATA section including its delimiters.
+    span: Range<usize>,
+    /// The byte range of the CDATA text without its delimiters, if this is a CDATA section.
+    cdata: Option<Range<usize>>,
+    /// Is this text only whitespace? Always false for a CDATA section.
+    is_whitespace: bool,
+}
+
+/// A segment of an XML path, e.g. `PropertyGroup`, `PropertyGroup[2]` or `@android:versionCode`.
+enum XmlPathSegment {
+    Element(String, Option<usize>),
+    Attribute(String),
+}
+
+/// Apply the `xml` entries of a target to the content of an XML file.
+///
+/// Each entry sets the text of the elements, or the value of the attribute, selected by a path to the result
+/// of an expression. Only the selected text is changed, so the rest of the document is preserved byte-for-byte.
+pub(crate) fn update_xml(
+    target_file: &Path,
+    content: &str,
+    xml_node: &JsonNode,
+    context: &HashMapContext,
+    update_reports: &mut Vec<UpdateReport>,
+) -> Result<String, ScriptError> {
+    let root_element = parse_xml(content).map_err(|message| {
+        script_error!(
+            format!(
+                "File '{}' is not valid XML - {}",
+                target_file.display(),
+                message
+            ),
+            xml_node
+        )
+    })?;
+    let mut edits = vec![];
+
+    for entry_node in xml_node.get_array_iter()? {
+        let path_node = entry_node.get_object_entry("path")?;
+        let path = path_node.get_string();
+        let segments = parse_path(&path).map_err(|message| script_error!(message, path_node))?;
+        let value_node = entry_node.get_object_entry("value")?;
+        let value = evalexpr::eval_with_context(&value_node.get_string(), context)
+            .map_err(|e| script_error!(e.to_string(), value_node))?;
+        let text = match value {
+            Value::String(s) => s,
+            Value::Float(f) if f.is_finite() => format!("{:?}", f),
+            Value::Int(n) => n.to_string(),
+            Value::Boolean(b) => b.to_string(),
+            _ => {
+                return Err(script_error!(
+                    "'value' must evaluate to a string, integer, float or boolean",
+                    value_node
+                ));
+            }
+        };
+        let (attribute_name, element_segments) = match segments.split_last() {
+            Some((XmlPathSegment::Attribute(name), rest)) => (Some(name), rest),
+            _ => (None, segments.as_slice()),
+        };
+        let elements = select_elements(&root_element, element_segments);
+        let mut entry_edits = vec![];
+
+        for element in elements {
+            if let Some(attribute_name) = attribute_name {
+                if let Some((_, range)) = element
+                    .attributes
+                    .iter()
+                    .find(|(name, _)| name == attribute_name)
+                {
+                    let quote = content[..range.start].chars().next_back().unwrap_or('"');
+
+                    entry_edits.push((range.clone(), escape(&text, Some(quote))));
+                }
+            } else if !element.children.is_empty() {
+                return Err(script_error!(
+                    format!(
+                        "Path '{}' in '{}' selects an element with child elements",
+                        path,
+                        target_file.display()
+                    ),
+                    path_node
+                ));
+            } else if element.is_empty_tag {
+                entry_edits.push((
+                    element.content.clone(),
+                    format!(">{}</{}>", escape(&text, None), element.name),
+                ));
+            } else {
+                entry_edits.extend(text_edits(content, element, &text));
+            }
+        }
+
+        if entry_edits.is_empty() {
+            return Err(script_error!(
+                format!("Path '{}' not found in '{}'", path, target_file.display()),
+                path_node
+            ));
+        }
+
+        update_reports.push(UpdateReport {
+            search: path,
+            match_count: entry_edits.len(),
+            skip_reason: None,
+        });
+
+        for (span, text) in entry_edits {
+            edits.retain(|(existing_span, _)| *existing_span != span);
+            edits.push((span, text));
+        }
+    }
+
+    Ok(json_edit::apply_edits(content, edits))
+}
+
+/// Get the edits that replace the text of an element without child elements, leaving any comments or
+/// processing instructions in its content unchanged.
+///
+/// The first text or CDATA section that is not just whitespace is replaced and any others are removed. If
+/// there are none, the whole content is replaced when it is only whitespace, otherwise the text is inserted
+/// at the start of the content.
+fn text_edits(content: &str, element: &XmlElement, text: &str) -> Vec<(Range<usize>, String)> {
+    let mut text_nodes = element.text_nodes.iter().filter(|node| !node.is_whitespace);
+
+    let Some(first_node) = text_nodes.next() else {
+        let range = if content[element.content.clone()].trim().is_empty() {
+            element.content.clone()
+        } else {
+            element.content.start..element.content.start
+        };
+
+        return vec![(range, escape(text, None))];
+    };
+    let first_edit = match first_node.cdata.as_ref().filter(|_| !text.contains("]]>")) {
+        Some(cdata) => (cdata.clone(), text.to_string()),
+        None => (first_node.span.clone(), escape(text, None)),
+    };
+
+    std::iter::once(first_edit)
+        .chain(text_nodes.map(|node| (node.span.clone(), String::new())))
+        .collect()
+}
+
+/// Parse an XML path such as `/Project/PropertyGroup/Version` or `manifest/@android:versionCode`.
+///
+/// The first segment must match the root element. An element segment can have a one-based index, as in
+/// `PropertyGroup[2]`, and the last segment can select an attribute.
+fn parse_path(path: &str) -> Result<Vec<XmlPathSegment>, String> {
+    let parts: Vec<&str> = path.strip_prefix('/').unwrap_or(path).split('/').collect();
+    let mut segments = vec![];
+
+    for (i, part) in parts.iter().enumerate() {
+        if let Some(name) = part.strip_prefix('@') {
+            if i + 1 != parts.len() || i == 0 {
+                return Err(format!(
+                    "XML path '{}' can only select an attribute in its last segment",
+                    path
+                ));
+            }
+
+            if name.is_empty() {
+                return Err(format!("XML path '{}' has an empty attribute name", path));
+            }
+
+            segments.push(XmlPathSegment::Attribute(name.to_string()));
+            continue;
+        }
+
+        let (name, index) = match part.strip_suffix(']').and_then(|s| s.split_once('[')) {
+            Some((name, index)) => match index.parse::<usize>() {
+                Ok(index) if index > 0 => (name, Some(index)),
+                _ => {
+                    return Err(format!(
+                        "XML path '{}' has an invalid index '[{}]', which must be one or more",
+                        path, index
+                    ));
+                }
+            },
+            None => (*part, None),
+        };
+
+        if name.is_empty() {
+            return Err(format!("XML path '{}' has an empty segment", path));
+        }
+
+        segments.push(XmlPathSegment::Element(name.to_string(), index));
+    }
+
+    Ok(segments)
+}
+
+/// Select the elements matching a path, starting with the root element.
+///
+/// Within a property list `dict` element, a segment also selects the value element following the `key`
+/// element with the segment as its text, e.g. `/plist/dict/CFBundleVersion`.
+fn select_elements<'a>(
+    root_element: &'a XmlElement,
+    segments: &[XmlPathSegment],
+) -> Vec<&'a XmlElement> {
+    let mut elements = vec![];
+
+    if let Some((XmlPathSegment::Element(name, index), rest)) = segments.split_first()
+        && root_element.name == *name
+        && index.is_none_or(|index| index == 1)
+    {
+        elements.push(root_element);
+
+        for segment in rest {
+            let XmlPathSegment::Element(name, index) = segment else {
+                return vec![];
+            };
+
+            elements = elements
+                .into_iter()
+                .flat_map(|element| {
+                    let matches = child_elements(element, name);
+
+                    match index {
+                        Some(index) => matches.into_iter().skip(index - 1).take(1).collect(),
+                        None => matches,
+                    }
+                })
+                .collect();
+        }
+    }
+
+    elements
+}
+
+fn child_elements<'a>(element: &'a XmlElement, name: &str) -> Vec<&'a XmlElement> {
+    let is_dict = element.name.rsplit(':').next() == Some("dict");
+    let mut matches = vec![];
+    let mut children = element.children.iter().peekable();
+
+    while let Some(child) = children.next() {
+        if child.name == name {
+            matches.push(child);
+        } else if is_dict
+            && child.name == "key"
+            && child.text == name
+            && let Some(value_element) = children.next_if(|c| c.name != "key")
+        {
+            matches.push(value_element);
+        }
+    }
+
+    matches
+}
+
+/// Parse the elements of an XML document, returning the root element.
+fn parse_xml(content: &str) -> Result<XmlElement, String> {
+    let mut stack: Vec<XmlElement> = vec![];
+    let mut root_element = None;
+
+    for token in Tokenizer::from(content) {
+        match token.map_err(|e| e.to_string())? {
+            Token::ElementStart {
+                prefix,
+                local,
+                span,
+            } => {
+                if root_element.is_some() {
+                    return Err(format!(
+                        "unexpected element after the root element at byte {}",
+                        span.start()
+                    ));
+                }
+
+                stack.push(XmlElement {
+                    name: qualified_name(prefix.as_str(), local.as_str()),
+                    attributes: vec![],
+                    content: 0..0,
+                    is_empty_tag: false,
+                    text_nodes: vec![],
+                    text: String::new(),
+                    children: vec![],
+                });
+            }
+            Token::Attribute {
+                prefix,
+                local,
+                value,
+                ..
+            } => {
+                if let Some(element) = stack.last_mut() {
+                    element.attributes.push((
+                        qualified_name(prefix.as_str(), local.as_str()),
+                        value.range(),
+                    ));
+                }
+            }
+            Token::ElementEnd { end, span } => {
+                let element = match end {
+                    ElementEnd::Open => {
+                        if let Some(element) = stack.last_mut() {
+                            element.content = span.end()..span.end();
+                        }
+
+                        continue;
+                    }
+                    ElementEnd::Empty => {
+                        let mut element = stack.pop().ok_or("unexpected '/>'")?;
+
+                        element.content = span.range();
+                        element.is_empty_tag = true;
+                        element
+                    }
+                    ElementEnd::Close(prefix, local) => {
+                        let mut element = stack.pop().ok_or("unexpected closing tag")?;
+                        let name = qualified_name(prefix.as_str(), local.as_str());
+
+                        if element.name != name {
+                            return Err(format!(
+                                "closing tag '{}' does not match opening tag '{}' at byte {}",
+                                name,
+                                element.name,
+                                span.start()
+                            ));
+                        }
+
+                        element.content.end = span.start();
+                        element
+                    }
+                };
+
+                match stack.last_mut() {
+                    Some(parent) => parent.children.push(element),
+                    None => root_element = Some(element),
+                }
+            }
+            Token::Text { text } => {
+                if let Some(element) = stack.last_mut() {
+                    element.text.push_str(&unescape(text.as_str()));
+                    element.text_nodes.push(XmlTextNode {
+                        span: text.range(),
+                        cdata: None,
+                        is_whitespace: text.as_str().trim().is_empty(),
+                    });
+                }
+            }
+            Token::Cdata { text, span } => {
+                if let Some(element) = stack.last_mut() {
+                    element.text.push_str(text.as_str());
+                    element.text_nodes.push(XmlTextNode {
+                        span: span.range(),
+                        cdata: Some(text.range()),
+                        is_whitespace: false,
+                    });
+                }
+            }
+            _ => (),
+        }
+    }
+
+    match (root_element, stack.last()) {
+        (Some(root_element), None) => Ok(root_element),
+        (_, Some(element)) => Err(format!("element '{}' is not closed", element.name)),
+        (None, None) => Err("there is no root element".to_string()),
+    }
+}
+
+fn qualified_name(prefix: &str, local: &str) -> String {
+    if prefix.is_empty() {
+        local.to_string()
+    } else {
+        format!("{}:{}", prefix, local)
+    }
+}
+
+/// Escape text for element content, or for an attribute value delimited by the given quote.
+fn escape(s: &str, quote: Option<char>) -> String {
+    let mut result = String::with_capacity(s.len());
+
+    for c in s.chars() {
+        match c {
+            '&' => result.push_str("&amp;"),
+            '<' => result.push_str("&lt;"),
+            '>' if quote.is_none() => result.push_str("&gt;"),
+            '"' if quote == Some('"') => result.push_str("&quot;"),
+            '\'' if quote == Some('\'') => result.push_str("&apos;"),
+            c => result.push(c),
+        }
+    }
+
+    result
+}
+
+/// Unescape the predefined entities and character references in text.
+fn unescape(s: &str) -> String {
+    let mut result = String::with_capacity(s.len());
+    let mut rest = s;
+
+    while let Some(start) = rest.find('&') {
+        result.push_str(&rest[..start]);
+        rest = &rest[start..];
+
+        let Some(end) = rest.find(';') else {
+            break;
+        };
+        let c = match &rest[1..end] {
+            "amp" => Some('&'),
+            "lt" => Some('<'),
+            "gt" => Some('>'),
+            "quot" => Some('"'),
+            "apos" => Some('\''),
+            reference => reference
+                .strip_prefix("#x")
+                .and_then(|hex| u32::from_str_radix(hex, 16).ok())
+                .or_else(|| reference.strip_prefix('#')?.parse().ok())
+                .and_then(char::from_u32),
+        };
+
+        match c {
+            Some(c) => {
+                result.push(c);
+                rest = &rest[end + 1..];
+            }
+            None => {
+                result.push('&');
+                rest = &rest[1..];
+            }
+        }
+    }
+
+    result.push_str(rest);
+    result
+}
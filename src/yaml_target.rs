@@ -0,0 +1,294 @@
+use crate::{
+    ScriptError, UpdateReport, json_edit,
+    json_node_extra::*,
+    key_path::{self, PathSegment},
+    script_error,
+};
+use evalexpr::{HashMapContext, Value};
+use json5_nodes::{JsonNode, Location};
+use regex::Regex;
+use std::{ops::Range, path::Path, sync::LazyLock};
+use yaml_rust2::{
+    Event,
+    parser::Parser,
+    scanner::{Marker, TScalarStyle},
+};
+
+/// A YAML node with just enough information to find and replace scalar values.
+enum YamlNode {
+    Scalar {
+        value: String,
+        style: TScalarStyle,
+        marker: Marker,
+        in_flow: bool,
+    },
+    Sequence(Vec<YamlNode>),
+    Mapping(Vec<(String, YamlNode)>),
+    Alias,
+}
+
+/// Plain scalars that would not be read back as strings, per the YAML core schema.
+static NON_STRING_PLAIN_RE: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(
+        r"^(?:~|null|Null|NULL|true|True|TRUE|false|False|FALSE|[-+]?(?:\.[0-9]+|[0-9]+(?:\.[0-9]*)?)(?:[eE][-+]?[0-9]+)?|0o[0-7]+|0x[0-9a-fA-F]+|[-+]?\.(?:inf|Inf|INF)|\.(?:nan|NaN|NAN))$",
+    )
+    .unwrap()
+});
+
+/// Apply the `yaml` entries of a target to the content of a YAML file.
+///
+/// Each entry replaces the scalar at a key path with the result of an expression. Only the text of the
+/// scalar is changed and its quoting style is kept, so comments, anchors and formatting are preserved.
+pub(crate) fn update_yaml(
+    target_file: &Path,
+    content: &str,
+    yaml_node: &JsonNode,
+    context: &HashMapContext,
+    update_reports: &mut Vec<UpdateReport>,
+) -> Result<String, ScriptError> {
+    let root_node = parse_yaml(content).map_err(|message| {
+        script_error!(
+            format!(
+                "File '{}' is not valid YAML - {}",
+                target_file.display(),
+                message
+            ),
+            yaml_node
+        )
+    })?;
+    let mut edits = vec![];
+
+    for entry_node in yaml_node.get_array_iter()? {
+        let path_node = entry_node.get_object_entry("path")?;
+        let path = path_node.get_string();
+        let segments =
+            key_path::parse(&path).map_err(|message| script_error!(message, path_node))?;
+        let value_node = entry_node.get_object_entry("value")?;
+        let value = evalexpr::eval_with_context(&value_node.get_string(), context)
+            .map_err(|e| script_error!(e.to_string(), value_node))?;
+        let path_error = |reason: &str| {
+            script_error!(
+                format!(
+                    "Key path '{}' {} in '{}'",
+                    path,
+                    reason,
+                    target_file.display()
+                ),
+                path_node
+            )
+        };
+        let YamlNode::Scalar {
+            value: old_value,
+            style,
+            marker,
+            in_flow,
+        } = node_at_path(&root_node, &segments).ok_or_else(|| path_error("not found"))?
+        else {
+            return Err(path_error("is not a scalar value"));
+        };
+        let span = scalar_span(content, old_value, *style, marker, *in_flow)
+            .ok_or_else(|| path_error("is a multi-line or block scalar which cannot be updated"))?;
+        let text = format_scalar(&value, *style).ok_or_else(|| {
+            script_error!(
+                "'value' must evaluate to a string, integer, float or boolean",
+                value_node
+            )
+        })?;
+
+        edits.retain(|(existing_span, _)| *existing_span != span);
+        edits.push((span, text));
+        update_reports.push(UpdateReport {
+            search: path,
+            match_count: 1,
+            skip_reason: None,
+        });
+    }
+
+    Ok(json_edit::apply_edits(content, edits))
+}
+
+/// Parse the first document in a YAML file.
+fn parse_yaml(content: &str) -> Result<YamlNode, String> {
+    let mut parser = Parser::new_from_str(content);
+
+    loop {
+        match parser.next_token().map_err(|e| e.to_string())? {
+            (Event::StreamEnd, _) => return Ok(YamlNode::Mapping(vec![])),
+            (Event::DocumentStart, _) => break,
+            _ => (),
+        }
+    }
+
+    parse_node(content, &mut parser, false)
+}
+
+fn parse_node(
+    content: &str,
+    parser: &mut Parser<std::str::Chars>,
+    in_flow: bool,
+) -> Result<YamlNode, String> {
+    let (event, marker) = parser.next_token().map_err(|e| e.to_string())?;
+    let is_flow =
+        |marker: &Marker| in_flow || char_at(content, marker).is_some_and(|c| c == '[' || c == '{');
+
+    match event {
+        Event::Scalar(value, style, ..) => Ok(YamlNode::Scalar {
+            value,
+            style,
+            marker,
+            in_flow,
+        }),
+        Event::Alias(_) => Ok(YamlNode::Alias),
+        Event::SequenceStart(..) => {
+            let in_flow = is_flow(&marker);
+            let mut items = vec![];
+
+            while !matches!(
+                parser.peek().map_err(|e| e.to_string())?.0,
+                Event::SequenceEnd
+            ) {
+                items.push(parse_node(content, parser, in_flow)?);
+            }
+
+            parser.next_token().map_err(|e| e.to_string())?;
+            Ok(YamlNode::Sequence(items))
+        }
+        Event::MappingStart(..) => {
+            let in_flow = is_flow(&marker);
+            let mut entries = vec![];
+
+            while !matches!(
+                parser.peek().map_err(|e| e.to_string())?.0,
+                Event::MappingEnd
+            ) {
+                let key = match parse_node(content, parser, in_flow)? {
+                    YamlNode::Scalar { value, .. } => value,
+                    _ => String::new(),
+                };
+
+                entries.push((key, parse_node(content, parser, in_flow)?));
+            }
+
+            parser.next_token().map_err(|e| e.to_string())?;
+            Ok(YamlNode::Mapping(entries))
+        }
+        event => Err(format!("unexpected event {:?}", event)),
+    }
+}
+
+/// The byte offset of a marker. The marker index counts characters, so use the line and column.
+fn marker_offset(content: &str, marker: &Marker) -> Option<usize> {
+    json_edit::location_offset(
+        content,
+        Location {
+            line: marker.line(),
+            column: marker.col() + 1,
+        },
+    )
+}
+
+fn char_at(content: &str, marker: &Marker) -> Option<char> {
+    content[marker_offset(content, marker)?..].chars().next()
+}
+
+fn node_at_path<'a>(node: &'a YamlNode, segments: &[PathSegment]) -> Option<&'a YamlNode> {
+    let Some((segment, rest)) = segments.split_first() else {
+        return Some(node);
+    };
+    let child_node = match (node, segment) {
+        (YamlNode::Mapping(entries), PathSegment::Key(key)) => {
+            entries.iter().find(|(k, _)| k == key).map(|(_, n)| n)
+        }
+        (YamlNode::Mapping(entries), PathSegment::Index(index)) => entries
+            .iter()
+            .find(|(k, _)| *k == index.to_string())
+            .map(|(_, n)| n),
+        (YamlNode::Sequence(items), PathSegment::Index(index)) => items.get(*index),
+        _ => None,
+    }?;
+
+    node_at_path(child_node, rest)
+}
+
+/// Get the byte range of a single line flow scalar.
+fn scalar_span(
+    content: &str,
+    value: &str,
+    style: TScalarStyle,
+    marker: &Marker,
+    in_flow: bool,
+) -> Option<Range<usize>> {
+    let start = marker_offset(content, marker)?;
+    let rest = &content[start..];
+    let span = match style {
+        TScalarStyle::DoubleQuoted => json_edit::scalar_span(content, start)?,
+        TScalarStyle::SingleQuoted => {
+            let mut chars = rest.char_indices().skip(1).peekable();
+
+            loop {
+                match chars.next()? {
+                    (_, '\'') if chars.next_if(|(_, c)| *c == '\'').is_some() => (),
+                    (offset, '\'') => break start..start + offset + 1,
+                    _ => (),
+                }
+            }
+        }
+        TScalarStyle::Plain => {
+            let mut len = rest.find(['\n', '\r']).unwrap_or(rest.len());
+
+            if let Some(comment_offset) = rest[..len].find(" #") {
+                len = comment_offset;
+            }
+
+            if in_flow && let Some(flow_offset) = rest[..len].find([',', ']', '}']) {
+                len = flow_offset;
+            }
+
+            let text = rest[..len].trim_end();
+
+            if text != value {
+                return None;
+            }
+
+            start..start + text.len()
+        }
+        TScalarStyle::Literal | TScalarStyle::Folded => return None,
+    };
+
+    if content[span.clone()].contains('\n') {
+        None
+    } else {
+        Some(span)
+    }
+}
+
+/// Format a value as a YAML scalar in the given style.
+fn format_scalar(value: &Value, style: TScalarStyle) -> Option<String> {
+    let s = match value {
+        Value::String(s) => s.clone(),
+        Value::Float(f) if f.is_finite() => format!("{:?}", f),
+        Value::Int(n) => n.to_string(),
+        Value::Boolean(b) => b.to_string(),
+        _ => return None,
+    };
+
+    match style {
+        TScalarStyle::SingleQuoted => Some(format!("'{}'", s.replace('\'', "''"))),
+        TScalarStyle::Plain if !matches!(value, Value::String(_)) || is_plain_safe(&s) => Some(s),
+        _ => json_edit::format_json_value(&Value::String(s)),
+    }
+}
+
+/// Can a string be written as a plain scalar and still be read back as the same string?
+fn is_plain_safe(s: &str) -> bool {
+    !s.is_empty()
+        && s.trim() == s
+        && !s.starts_with([
+            '-', '?', ':', ',', '[', ']', '{', '}', '#', '&', '*', '!', '|', '>', '\'', '"', '%',
+            '@', '`',
+        ])
+        && !s.contains(": ")
+        && !s.contains(" #")
+        && !s.contains(|c: char| c.is_control())
+        && !NON_STRING_PLAIN_RE.is_match(s)
+}
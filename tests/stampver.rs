@@ -1,4 +1,136 @@
 use duct::cmd;
+use evalexpr::HashMapContext;
+use json5_nodes::JsonNode;
+use stampver::{RunReport, ScriptError, StampVerTool, TargetAction, Transaction};
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    process::Output,
+    time::{Duration, SystemTime},
+};
+use tempfile::TempDir;
+
+/// Write a script file named `version.json5` to a new temporary directory.
+fn write_script(script_content: &str) -> (TempDir, PathBuf) {
+    let temp_dir = tempfile::tempdir().unwrap();
+    let script_file = temp_dir.path().join("version.json5");
+
+    fs::write(&script_file, script_content).unwrap();
+
+    (temp_dir, script_file)
+}
+
+/// A command that runs `stampver` on a script file, capturing its output without failing on an error.
+fn stampver(script_file: &Path, args: &[&str]) -> duct::Expression {
+    cmd(
+        env!("CARGO_BIN_EXE_stampver"),
+        ["-i".as_ref(), script_file.as_os_str()]
+            .into_iter()
+            .chain(args.iter().map(|arg| arg.as_ref())),
+    )
+    .stdout_capture()
+    .stderr_capture()
+    .unchecked()
+}
+
+/// A script file read by a tool, with a run context for running it.
+struct ScriptRun<'a> {
+    tool: &'a StampVerTool,
+    content: String,
+    root_node: JsonNode,
+    script_file: PathBuf,
+    run_context: HashMapContext,
+}
+
+impl<'a> ScriptRun<'a> {
+    /// Read a script file and create a run context for it.
+    fn new(tool: &'a StampVerTool, script_file: &Path) -> ScriptRun<'a> {
+        let (content, root_node, script_file) =
+            tool.read_script_file(script_file.to_path_buf()).unwrap();
+        let run_context = tool.create_run_context(&root_node).unwrap();
+
+        ScriptRun {
+            tool,
+            content,
+            root_node,
+            script_file,
+            run_context,
+        }
+    }
+
+    /// Validate the script and run an operation.
+    fn run_operation(&mut self, operation: &str) {
+        self.tool.validate_script_file(&self.root_node).unwrap();
+        self.tool
+            .run_operation(
+                Some(operation.to_string()),
+                &self.root_node,
+                &mut self.run_context,
+            )
+            .unwrap();
+    }
+
+    /// Process the targets for every file under the script file directory.
+    fn process_targets(&mut self, update: bool) -> Result<RunReport, ScriptError> {
+        let filter_paths = self
+            .tool
+            .validate_filter_paths(&[], &self.script_file)
+            .unwrap();
+
+        self.tool.process_targets(
+            &self.script_file,
+            &self.root_node,
+            update,
+            &mut self.run_context,
+            &filter_paths,
+        )
+    }
+}
+
+/// A git repository with a fixed author and committer and no global config.
+struct GitRepo<'a> {
+    dir: &'a Path,
+}
+
+impl<'a> GitRepo<'a> {
+    /// Create a repository with a `main` branch in a directory.
+    fn init(dir: &'a Path) -> GitRepo<'a> {
+        let repo = GitRepo { dir };
+
+        repo.git(&["init", "--quiet", "--initial-branch=main"]);
+
+        repo
+    }
+
+    /// Run a command in the repository directory, capturing its output without failing on an error.
+    fn run(&self, expression: duct::Expression) -> Output {
+        expression
+            .dir(self.dir)
+            .env("GIT_AUTHOR_NAME", "Test")
+            .env("GIT_AUTHOR_EMAIL", "test@example.com")
+            .env("GIT_COMMITTER_NAME", "Test")
+            .env("GIT_COMMITTER_EMAIL", "test@example.com")
+            .env("GIT_CONFIG_GLOBAL", "/dev/null")
+            .stdout_capture()
+            .stderr_capture()
+            .unchecked()
+            .run()
+            .unwrap()
+    }
+
+    /// Run git, returning its trimmed output.
+    fn git(&self, args: &[&str]) -> String {
+        String::from_utf8(self.run(cmd("git", args)).stdout)
+            .unwrap()
+            .trim()
+            .to_string()
+    }
+
+    /// Make an empty commit.
+    fn commit(&self, message: &str) {
+        self.git(&["commit", "--quiet", "--allow-empty", "-m", message]);
+    }
+}
 
 #[test]
 fn test_all() {
@@ -24,4 +156,2140 @@ fn test_all() {
     assert!(stderr.contains("Would update"));
     assert!(stderr.contains("Would write"));
     assert!(stderr.contains("Would copy"));
+    assert!(stderr.contains("Would update 'vars' -> '"), "{}", stderr);
+}
+
+#[test]
+fn test_run_report() {
+    let tool = StampVerTool::new();
+    let mut script = ScriptRun::new(&tool, Path::new("examples/version.json5"));
+
+    script.run_operation("incrPatch");
+
+    let run_report = script.process_targets(false).unwrap();
+    let actions: Vec<_> = run_report.files().map(|file| file.action).collect();
+
+    assert_eq!(
+        actions,
+        vec![
+            TargetAction::Json,
+            TargetAction::Update,
+            TargetAction::Write,
+            TargetAction::Copy
+        ]
+    );
+    assert!(run_report.files().all(|file| !file.applied));
+    assert!(run_report.files().all(|file| file.is_changed()));
+
+    let version_ts = &run_report.targets[1].files[0];
+
+    assert_eq!(version_ts.updates.len(), 2);
+    assert!(version_ts.updates.iter().all(|u| u.match_count == 1));
+}
+
+#[test]
+fn test_diff() {
+    let output = cmd![
+        "cargo",
+        "run",
+        "--",
+        "-i",
+        "examples/version.json5",
+        "--diff",
+        "incrMajor"
+    ]
+    .stdout_capture()
+    .stderr_capture()
+    .unchecked()
+    .run()
+    .unwrap();
+
+    let stdout = String::from_utf8(output.stdout).unwrap();
+
+    assert!(output.status.success());
+    assert!(stdout.contains("--- a/package.json\n+++ b/package.json\n"));
+    assert!(stdout.contains("+    \"version\": \"4.0.0\""));
+    assert!(stdout.contains("--- /dev/null\n+++ b/version.desc.txt\n"));
+    assert!(stdout.contains("+    major: 4,"));
+}
+
+#[test]
+fn test_rollback() {
+    let script_content = r#"{
+  vars: {
+    major: 1,
+    minor: 0,
+    patch: 0,
+    tz: "UTC",
+  },
+  calcVars: {},
+  operations: {
+    incrPatch: "patch += 1",
+  },
+  targets: [
+    {
+      description: "Version file",
+      files: ["version.txt"],
+      updates: [
+        {
+          search: '^(?P<begin>version = )\\d+\\.\\d+\\.\\d+$',
+          replace: 'begin + str::from(major) + "." + str::from(minor) + "." + str::from(patch)',
+        },
+      ],
+    },
+    {
+      description: "Tag file",
+      files: ["version.tag"],
+      write: '"v" + str::from(major) + "." + str::from(minor) + "." + str::from(patch)',
+    },
+  ],
+}
+"#;
+    let (temp_dir, script_file) = write_script(script_content);
+    let tag_file = temp_dir.path().join("version.tag");
+
+    fs::write(temp_dir.path().join("version.txt"), "version = 1.0.0\n").unwrap();
+    fs::write(&tag_file, "v1.0.0").unwrap();
+
+    let tool = StampVerTool::new();
+    let mut script = ScriptRun::new(&tool, &script_file);
+    let filter_paths = tool.validate_filter_paths(&[], &script_file).unwrap();
+    let mut transaction = Transaction::new();
+
+    script.run_operation("incrPatch");
+    tool.stage_targets(
+        &script.script_file,
+        &script.root_node,
+        &mut script.run_context,
+        &filter_paths,
+        &mut transaction,
+    )
+    .unwrap();
+    tool.stage_script_files(
+        &script.script_file,
+        script.content,
+        &[(&script.root_node, &script.run_context)],
+        &mut transaction,
+    )
+    .unwrap();
+
+    // Replace a staged file with a directory so that it cannot be renamed into place
+    fs::remove_file(&tag_file).unwrap();
+    fs::create_dir(&tag_file).unwrap();
+
+    let err = transaction.commit().unwrap_err();
+
+    assert!(err.message.contains("rolled back"), "{}", err);
+    assert_eq!(
+        fs::read_to_string(temp_dir.path().join("version.txt")).unwrap(),
+        "version = 1.0.0\n"
+    );
+    assert_eq!(fs::read_to_string(&script_file).unwrap(), script_content);
+    assert_eq!(fs::read_dir(temp_dir.path()).unwrap().count(), 3);
+}
+
+#[test]
+fn test_unchanged_files_are_not_written() {
+    let (temp_dir, script_file) = write_script(
+        r#"{
+  vars: { major: 1, minor: 0, patch: 0 },
+  calcVars: {},
+  operations: { incrPatch: "patch += 1" },
+  targets: [
+    { description: "Version", files: ["version.txt"], write: "str::from(patch)" },
+    { description: "Major", files: ["major.txt"], write: "str::from(major)" },
+  ],
+}
+"#,
+    );
+    let major_file = temp_dir.path().join("major.txt");
+    let modified = SystemTime::UNIX_EPOCH + Duration::from_secs(1_000_000_000);
+
+    fs::write(&major_file, "1").unwrap();
+    fs::File::options()
+        .write(true)
+        .open(&major_file)
+        .unwrap()
+        .set_modified(modified)
+        .unwrap();
+
+    let output = stampver(&script_file, &["-u", "incrPatch"]).run().unwrap();
+
+    assert!(output.status.success());
+    assert_eq!(
+        fs::read_to_string(temp_dir.path().join("version.txt")).unwrap(),
+        "1"
+    );
+    assert_eq!(
+        fs::metadata(&major_file).unwrap().modified().unwrap(),
+        modified
+    );
+}
+
+#[test]
+fn test_update_script_file_formatting() {
+    let (_temp_dir, script_file) = write_script(
+        r#"{
+  // patch: 99 is not a var
+  vars: { major: 1, "minor": 2, 'patch': 3 /* patch: 3 */, label: "it's", tz: "UTC" }, // minor: 2
+  calcVars: {},
+  operations: {
+    bump: 'major += 1; minor = 0; patch = 10; label = "say \\"hi\\""',
+  },
+  targets: [{ description: "Text", files: ["version.txt"], write: "str::from(major)" }],
+}
+"#,
+    );
+    let tool = StampVerTool::new();
+    let mut script = ScriptRun::new(&tool, &script_file);
+
+    script.run_operation("bump");
+
+    let file_report = tool
+        .update_script_file(
+            &script.script_file,
+            script.content,
+            &script.root_node,
+            &script.run_context,
+            true,
+        )
+        .unwrap();
+
+    assert!(file_report.applied);
+    assert!(
+        fs::read_to_string(&script_file)
+            .unwrap()
+            .contains("vars: { major: 2, \"minor\": 0, 'patch': 10")
+    );
+    assert_eq!(
+        file_report.diff.unwrap(),
+        r#"--- a/version.json5
++++ b/version.json5
+@@ -1,6 +1,6 @@
+ {
+   // patch: 99 is not a var
+-  vars: { major: 1, "minor": 2, 'patch': 3 /* patch: 3 */, label: "it's", tz: "UTC" }, // minor: 2
++  vars: { major: 2, "minor": 0, 'patch': 10 /* patch: 3 */, label: 'say "hi"', tz: "UTC" }, // minor: 2
+   calcVars: {},
+   operations: {
+     bump: 'major += 1; minor = 0; patch = 10; label = "say \\"hi\\""',
+"#
+    );
+}
+
+#[test]
+fn test_target_file_globs() {
+    let (temp_dir, script_file) = write_script(
+        r#"{
+  vars: { major: 1, minor: 0, patch: 0, tz: "UTC" },
+  calcVars: {},
+  operations: { incrPatch: "patch += 1" },
+  targets: [
+    {
+      description: "Cargo packages",
+      files: ["crates/*/Cargo.toml", "!crates/skip/**"],
+      respectGitignore: true,
+      updates: [
+        {
+          search: '^(?P<begin>version = ")\\d+\\.\\d+\\.\\d+(?P<end>")$',
+          replace: 'begin + str::from(major) + "." + str::from(minor) + "." + str::from(patch) + end',
+        },
+      ],
+    },
+    {
+      description: "Docs",
+      files: ["docs/"],
+      write: '"1.0.1"',
+    },
+    {
+      description: "API docs",
+      files: ["docs/api"],
+      write: '"1.0.1"',
+    },
+  ],
+}
+"#,
+    );
+
+    fs::write(temp_dir.path().join(".gitignore"), "/crates/ignored\n").unwrap();
+
+    for name in ["a", "b", "ignored", "skip"] {
+        let crate_dir = temp_dir.path().join("crates").join(name);
+
+        fs::create_dir_all(&crate_dir).unwrap();
+        fs::write(crate_dir.join("Cargo.toml"), "version = \"1.0.0\"\n").unwrap();
+    }
+
+    fs::create_dir_all(temp_dir.path().join("docs/api")).unwrap();
+    fs::write(temp_dir.path().join("docs/VERSION"), "1.0.0").unwrap();
+    fs::write(temp_dir.path().join("docs/api/VERSION"), "1.0.0").unwrap();
+
+    let tool = StampVerTool::new();
+    let mut script = ScriptRun::new(&tool, &script_file);
+
+    script.run_operation("incrPatch");
+
+    let run_report = script.process_targets(false).unwrap();
+    let base_dir = script.script_file.parent().unwrap();
+    let files: Vec<_> = run_report
+        .files()
+        .map(|file| file.path.strip_prefix(base_dir).unwrap().to_path_buf())
+        .collect();
+
+    assert_eq!(
+        files,
+        vec![
+            PathBuf::from("crates/a/Cargo.toml"),
+            PathBuf::from("crates/b/Cargo.toml"),
+            PathBuf::from("docs/VERSION"),
+            PathBuf::from("docs/api/VERSION"),
+            PathBuf::from("docs/api/VERSION"),
+        ]
+    );
+
+    let app_script_file = temp_dir.path().join("app/version.json5");
+
+    fs::create_dir(temp_dir.path().join("app")).unwrap();
+    fs::write(
+        &app_script_file,
+        r#"{
+  vars: { major: 1 },
+  calcVars: {},
+  operations: {},
+  targets: [{ description: "Docs", files: ["../docs/"], write: '"1.0.1"' }],
+}
+"#,
+    )
+    .unwrap();
+
+    let mut script = ScriptRun::new(&tool, &app_script_file);
+    let err = script.process_targets(false).unwrap_err();
+
+    assert!(
+        err.message
+            .starts_with("Target directory '../docs/' is outside the script file directory"),
+        "{}",
+        err
+    );
+}
+
+#[test]
+fn test_toml_target() {
+    let (temp_dir, script_file) = write_script(
+        r#"{
+  vars: { major: 1, minor: 2, patch: 3, tz: "UTC" },
+  calcVars: {},
+  operations: { incrMinor: "minor += 1; patch = 0" },
+  targets: [
+    {
+      description: "Cargo workspace",
+      files: ["Cargo.toml"],
+      toml: [
+        {
+          path: "workspace.package.version",
+          value: 'str::from(major) + "." + str::from(minor) + "." + str::from(patch)',
+        },
+        { path: "bin.1.name", value: '"tool-" + str::from(major)' },
+        { path: 'workspace.metadata."release.build"', value: "minor * 100" },
+      ],
+    },
+  ],
+}
+"#,
+    );
+
+    fs::write(
+        temp_dir.path().join("Cargo.toml"),
+        r#"[workspace.package]
+version = "1.2.3" # The version
+edition = "2024"
+
+[workspace.metadata]
+"release.build" = 200
+
+[dependencies]
+serde = { version = "1.2.3" }
+
+[[bin]]
+name = "main"
+
+[[bin]]
+name = "tool"
+"#,
+    )
+    .unwrap();
+
+    let tool = StampVerTool::new();
+    let mut script = ScriptRun::new(&tool, &script_file);
+
+    script.run_operation("incrMinor");
+
+    let run_report = script.process_targets(false).unwrap();
+
+    assert_eq!(
+        run_report.targets[0].files[0].diff.as_deref().unwrap(),
+        r#"--- a/Cargo.toml
++++ b/Cargo.toml
+@@ -1,9 +1,9 @@
+ [workspace.package]
+-version = "1.2.3" # The version
++version = "1.3.0" # The version
+ edition = "2024"
+ 
+ [workspace.metadata]
+-"release.build" = 200
++"release.build" = 300
+ 
+ [dependencies]
+ serde = { version = "1.2.3" }
+@@ -12,4 +12,4 @@
+ name = "main"
+ 
+ [[bin]]
+-name = "tool"
++name = "tool-1"
+"#
+    );
+}
+
+#[test]
+fn test_json_target() {
+    let (temp_dir, script_file) = write_script(
+        r#"{
+  vars: { major: 1, minor: 2, patch: 3, build: 45, tz: "UTC" },
+  calcVars: {},
+  operations: { incrPatch: "patch += 1; build += 1" },
+  targets: [
+    {
+      description: "Expo app",
+      files: ["app.json"],
+      json: [
+        {
+          path: "/expo/version",
+          value: 'str::from(major) + "." + str::from(minor) + "." + str::from(patch)',
+        },
+        { path: "expo.android.versionCode", value: "build" },
+        { path: "/expo/ios/buildNumbers/1", value: "str::from(build)" },
+      ],
+    },
+  ],
+}
+"#,
+    );
+
+    fs::write(
+        temp_dir.path().join("app.json"),
+        r#"{
+  "expo": {
+    "name": "app", "version": "1.2.3",
+    "android": { "versionCode": 45 },
+    "ios": {
+      "buildNumbers": ["44", "45"]
+    }
+  }
+}
+"#,
+    )
+    .unwrap();
+
+    let tool = StampVerTool::new();
+    let mut script = ScriptRun::new(&tool, &script_file);
+
+    script.run_operation("incrPatch");
+
+    let run_report = script.process_targets(false).unwrap();
+
+    assert_eq!(
+        run_report.targets[0].files[0].diff.as_deref().unwrap(),
+        r#"--- a/app.json
++++ b/app.json
+@@ -1,9 +1,9 @@
+ {
+   "expo": {
+-    "name": "app", "version": "1.2.3",
+-    "android": { "versionCode": 45 },
++    "name": "app", "version": "1.2.4",
++    "android": { "versionCode": 46 },
+     "ios": {
+-      "buildNumbers": ["44", "45"]
++      "buildNumbers": ["44", "46"]
+     }
+   }
+ }
+"#
+    );
+
+    fs::write(
+        temp_dir.path().join("app.json"),
+        "{\n  \"expo\": {\n    \"name\": \"app\"\n  }\n}\n",
+    )
+    .unwrap();
+
+    let err = script.process_targets(false).unwrap_err();
+
+    assert!(err.message.contains("Key path '/expo/version' not found"));
+    assert!(err.message.ends_with("at 2:11"), "{}", err.message);
+    assert_eq!(err.location.unwrap().line, 11);
+}
+
+#[test]
+fn test_yaml_target() {
+    let (temp_dir, script_file) = write_script(
+        r#"{
+  vars: { major: 1, minor: 2, patch: 3, build: 45, tz: "UTC" },
+  calcVars: {},
+  operations: {
+    incrPatch: 'patch += 1; build += 1; nextVersion = str::from(major) + "." + str::from(minor) + "." + str::from(patch)',
+  },
+  targets: [
+    {
+      description: "Helm chart",
+      files: ["Chart.yaml"],
+      yaml: [
+        { path: "version", value: "nextVersion" },
+        { path: "appVersion", value: "nextVersion" },
+        { path: "annotations.build", value: "build" },
+        { path: "tags.1", value: '"v" + nextVersion' },
+      ],
+    },
+  ],
+}
+"#,
+    );
+
+    fs::write(
+        temp_dir.path().join("Chart.yaml"),
+        r#"# A chart
+apiVersion: v2
+name: app
+version: 1.2.3 # chart version
+appVersion: "1.2.3"
+defaults: &defaults
+  build: 45
+annotations:
+  <<: *defaults
+  build: 45
+tags: [stable, v1.2.3]
+"#,
+    )
+    .unwrap();
+
+    let tool = StampVerTool::new();
+    let mut script = ScriptRun::new(&tool, &script_file);
+
+    script.run_operation("incrPatch");
+
+    let run_report = script.process_targets(false).unwrap();
+
+    assert_eq!(run_report.targets[0].files[0].action, TargetAction::Yaml);
+    assert_eq!(
+        run_report.targets[0].files[0].diff.as_deref().unwrap(),
+        r#"--- a/Chart.yaml
++++ b/Chart.yaml
+@@ -1,11 +1,11 @@
+ # A chart
+ apiVersion: v2
+ name: app
+-version: 1.2.3 # chart version
+-appVersion: "1.2.3"
++version: 1.2.4 # chart version
++appVersion: "1.2.4"
+ defaults: &defaults
+   build: 45
+ annotations:
+   <<: *defaults
+-  build: 45
+-tags: [stable, v1.2.3]
++  build: 46
++tags: [stable, v1.2.4]
+"#
+    );
+
+    fs::write(temp_dir.path().join("Chart.yaml"), "name: app\n").unwrap();
+
+    let err = script.process_targets(false).unwrap_err();
+
+    assert!(err.message.contains("Key path 'version' not found"));
+    assert_eq!(err.location.unwrap().line, 12);
+}
+
+#[test]
+fn test_xml_target() {
+    let (temp_dir, script_file) = write_script(
+        r#"{
+  vars: { major: 1, minor: 2, patch: 3, build: 45, tz: "UTC" },
+  calcVars: {},
+  operations: {
+    incrPatch: 'patch += 1; build += 1; nextVersion = str::from(major) + "." + str::from(minor) + "." + str::from(patch)',
+  },
+  targets: [
+    {
+      description: "Project file",
+      files: ["App.csproj"],
+      xml: [
+        { path: "/Project/PropertyGroup/Version", value: "nextVersion" },
+        { path: "/Project/PropertyGroup/FileVersion", value: "nextVersion" },
+        { path: "/Project/PropertyGroup[2]/Description", value: '"Build <" + str::from(build) + ">"' },
+      ],
+    },
+    {
+      description: "Android manifest",
+      files: ["AndroidManifest.xml"],
+      xml: [
+        { path: "manifest/@android:versionCode", value: "build" },
+        { path: "manifest/@android:versionName", value: "nextVersion" },
+      ],
+    },
+    {
+      description: "Info.plist",
+      files: ["Info.plist"],
+      xml: [
+        { path: "/plist/dict/CFBundleShortVersionString", value: "nextVersion" },
+        { path: "/plist/dict/CFBundleVersion", value: "str::from(build)" },
+      ],
+    },
+  ],
+}
+"#,
+    );
+
+    fs::write(
+        temp_dir.path().join("App.csproj"),
+        r#"<Project Sdk="Microsoft.NET.Sdk">
+  <!-- Versions -->
+  <PropertyGroup>
+    <Version>1.2.3</Version>
+    <FileVersion><!-- set by stampver --> 1.2.3 <?build final?></FileVersion>
+  </PropertyGroup>
+  <PropertyGroup>
+    <Description/>
+  </PropertyGroup>
+</Project>
+"#,
+    )
+    .unwrap();
+    fs::write(
+        temp_dir.path().join("AndroidManifest.xml"),
+        r#"<?xml version="1.0" encoding="utf-8"?>
+<manifest xmlns:android="http://schemas.android.com/apk/res/android"
+    android:versionCode="45"
+    android:versionName='1.2.3' >
+</manifest>
+"#,
+    )
+    .unwrap();
+    fs::write(
+        temp_dir.path().join("Info.plist"),
+        r#"<?xml version="1.0" encoding="UTF-8"?>
+<!DOCTYPE plist PUBLIC "-//Apple//DTD PLIST 1.0//EN" "http://www.apple.com/DTDs/PropertyList-1.0.dtd">
+<plist version="1.0">
+<dict>
+	<key>CFBundleShortVersionString</key>
+	<string>1.2.3</string>
+	<key>CFBundleVersion</key>
+	<string><![CDATA[45]]></string>
+</dict>
+</plist>
+"#,
+    )
+    .unwrap();
+
+    let tool = StampVerTool::new();
+    let mut script = ScriptRun::new(&tool, &script_file);
+
+    script.run_operation("incrPatch");
+
+    let run_report = script.process_targets(false).unwrap();
+    let diffs: Vec<&str> = run_report
+        .files()
+        .map(|file_report| file_report.diff.as_deref().unwrap())
+        .collect();
+
+    assert_eq!(run_report.targets[0].files[0].action, TargetAction::Xml);
+    assert_eq!(
+        diffs,
+        [
+            r#"--- a/App.csproj
++++ b/App.csproj
+@@ -1,10 +1,10 @@
+ <Project Sdk="Microsoft.NET.Sdk">
+   <!-- Versions -->
+   <PropertyGroup>
+-    <Version>1.2.3</Version>
+-    <FileVersion><!-- set by stampver --> 1.2.3 <?build final?></FileVersion>
++    <Version>1.2.4</Version>
++    <FileVersion><!-- set by stampver -->1.2.4<?build final?></FileVersion>
+   </PropertyGroup>
+   <PropertyGroup>
+-    <Description/>
++    <Description>Build &lt;46&gt;</Description>
+   </PropertyGroup>
+ </Project>
+"#,
+            r#"--- a/AndroidManifest.xml
++++ b/AndroidManifest.xml
+@@ -1,5 +1,5 @@
+ <?xml version="1.0" encoding="utf-8"?>
+ <manifest xmlns:android="http://schemas.android.com/apk/res/android"
+-    android:versionCode="45"
+-    android:versionName='1.2.3' >
++    android:versionCode="46"
++    android:versionName='1.2.4' >
+ </manifest>
+"#,
+            r#"--- a/Info.plist
++++ b/Info.plist
+@@ -3,8 +3,8 @@
+ <plist version="1.0">
+ <dict>
+ 	<key>CFBundleShortVersionString</key>
+-	<string>1.2.3</string>
++	<string>1.2.4</string>
+ 	<key>CFBundleVersion</key>
+-	<string><![CDATA[45]]></string>
++	<string><![CDATA[46]]></string>
+ </dict>
+ </plist>
+"#,
+        ]
+    );
+
+    fs::write(
+        temp_dir.path().join("App.csproj"),
+        "<Project>\n  <PropertyGroup/>\n</Project>\n",
+    )
+    .unwrap();
+
+    let err = script.process_targets(false).unwrap_err();
+
+    assert!(
+        err.message
+            .contains("Path '/Project/PropertyGroup/Version' not found")
+    );
+    assert_eq!(err.location.unwrap().line, 12);
+}
+
+#[test]
+fn test_check() {
+    let script_content = r#"{
+  vars: { major: 1, minor: 0, patch: 0, tz: "UTC" },
+  calcVars: {},
+  operations: { incrPatch: "patch += 1" },
+  targets: [
+    {
+      description: "Version file",
+      files: ["version.txt"],
+      updates: [
+        {
+          search: '^(?P<begin>version = )\\d+\\.\\d+\\.\\d+$',
+          replace: 'begin + str::from(major) + "." + str::from(minor) + "." + str::from(patch)',
+        },
+      ],
+    },
+    { description: "Tag", files: ["version.tag"], write: '"v" + str::from(major)' },
+    { description: "Copy", files: ["copy.txt"], copyFrom: '"source.txt"' },
+  ],
+}
+"#;
+    let (temp_dir, script_file) = write_script(script_content);
+    let check = || {
+        let output = stampver(&script_file, &["check"]).run().unwrap();
+
+        (
+            output.status.success(),
+            String::from_utf8(output.stderr).unwrap(),
+        )
+    };
+
+    fs::write(temp_dir.path().join("version.txt"), "version = 1.0.0\n").unwrap();
+    fs::write(temp_dir.path().join("version.tag"), "v1").unwrap();
+    fs::write(temp_dir.path().join("source.txt"), "source\n").unwrap();
+    fs::write(temp_dir.path().join("copy.txt"), "source\n").unwrap();
+
+    let (success, stderr) = check();
+
+    assert!(success, "stderr: {}", stderr);
+    assert!(stderr.contains("All 3 target file(s) are in sync"));
+
+    fs::write(temp_dir.path().join("version.txt"), "version = 1.0.1\n").unwrap();
+    fs::write(temp_dir.path().join("version.tag"), "v2").unwrap();
+    fs::write(temp_dir.path().join("copy.txt"), "edited\n").unwrap();
+
+    let (success, stderr) = check();
+
+    assert!(!success);
+    assert!(stderr.contains("1 line(s) would be changed by 'updates'"));
+    assert!(stderr.contains("differs from the 'write' expression"));
+    assert!(stderr.contains("differs from the 'copyFrom' file"));
+    assert!(stderr.contains("3 target file(s) are out of sync"));
+    assert_eq!(
+        fs::read_to_string(temp_dir.path().join("version.txt")).unwrap(),
+        "version = 1.0.1\n"
+    );
+
+    fs::write(
+        &script_file,
+        script_content.replace("incrPatch:", "check: \"patch = 0\", incrPatch:"),
+    )
+    .unwrap();
+
+    let (success, stderr) = check();
+
+    assert!(!success);
+    assert!(stderr.contains("Operation 'check' is built in and cannot be defined in 'operations'"));
+}
+
+#[test]
+fn test_semver_functions() {
+    let (_temp_dir, script_file) = write_script(
+        r#"{
+  vars: { version: "1.2.3-beta.2+build.5", tz: "UTC" },
+  calcVars: {},
+  operations: {},
+  targets: [],
+}
+"#,
+    );
+
+    let tool = StampVerTool::new();
+    let ScriptRun { run_context, .. } = ScriptRun::new(&tool, &script_file);
+    let eval = |expression: &str| evalexpr::eval_with_context(expression, &run_context).unwrap();
+
+    assert_eq!(
+        eval("semver::format(1, 2, 3)"),
+        evalexpr::Value::from("1.2.3")
+    );
+    assert_eq!(
+        eval(r#"semver::format(1, 2, 3, "rc.1", "abc")"#),
+        evalexpr::Value::from("1.2.3-rc.1+abc")
+    );
+    assert_eq!(
+        eval("semver::parse(version)"),
+        evalexpr::Value::Tuple(vec![
+            evalexpr::Value::Int(1),
+            evalexpr::Value::Int(2),
+            evalexpr::Value::Int(3),
+            evalexpr::Value::from("beta.2"),
+            evalexpr::Value::from("build.5"),
+        ])
+    );
+    assert_eq!(
+        eval("semver::format(semver::parse(version))"),
+        evalexpr::Value::from("1.2.3-beta.2+build.5")
+    );
+
+    for (expression, expected) in [
+        (r#"semver::bump("1.2.3", "major")"#, "2.0.0"),
+        (r#"semver::bump("1.2.3", "minor")"#, "1.3.0"),
+        (r#"semver::bump("1.2.3", "patch")"#, "1.2.4"),
+        (r#"semver::bump("1.2.3", "prerelease")"#, "1.2.4-0"),
+        (r#"semver::bump(version, "prerelease")"#, "1.2.3-beta.3"),
+        (r#"semver::bump("1.2.3-rc", "prerelease")"#, "1.2.3-rc.0"),
+        (r#"semver::bump(version, "patch")"#, "1.2.3"),
+        (r#"semver::bump("1.3.0-rc.1", "minor")"#, "1.3.0"),
+        (r#"semver::bump("1.3.1-rc.1", "minor")"#, "1.4.0"),
+    ] {
+        assert_eq!(
+            eval(expression),
+            evalexpr::Value::from(expected),
+            "{}",
+            expression
+        );
+    }
+
+    for (expression, expected) in [
+        (r#"semver::compare("1.2.3", "1.2.4")"#, -1),
+        (r#"semver::compare("1.2.3+a", "1.2.3+b")"#, 0),
+        (r#"semver::compare("1.0.0-alpha", "1.0.0-alpha.1")"#, -1),
+        (r#"semver::compare("1.0.0-alpha.beta", "1.0.0-beta")"#, -1),
+        (r#"semver::compare("1.0.0-beta.11", "1.0.0-beta.2")"#, 1),
+        (r#"semver::compare("1.0.0-rc.1", "1.0.0")"#, -1),
+    ] {
+        assert_eq!(
+            eval(expression),
+            evalexpr::Value::Int(expected),
+            "{}",
+            expression
+        );
+    }
+
+    assert_eq!(
+        eval(r#"semver::satisfies("1.4.0", "^1.2")"#),
+        evalexpr::Value::Boolean(true)
+    );
+    assert_eq!(
+        eval(r#"semver::satisfies("2.0.0", "^1.2")"#),
+        evalexpr::Value::Boolean(false)
+    );
+    assert!(
+        evalexpr::eval_with_context(r#"semver::parse("1.2")"#, &run_context)
+            .unwrap_err()
+            .to_string()
+            .contains("'1.2' is not a valid semantic version")
+    );
+}
+
+#[test]
+fn test_prerelease_lifecycle() {
+    let (_temp_dir, script_file) = write_script(
+        r#"{
+  vars: { major: 1, minor: 2, patch: 3, channel: "", channelNum: 0, tz: "UTC" },
+  calcVars: {},
+  operations: {
+    incrMinor: "minor += 1; patch = 0",
+    incrPatch: "patch += 1",
+    bumpPatch: 'op::run("incrPatch")',
+  },
+  prerelease: {
+    channels: ["alpha", "beta", "rc"],
+    channelVar: "channel",
+    numberVar: "channelNum",
+    startOperation: "incrMinor",
+  },
+  targets: [{ description: "Version", files: ["version.txt"], write: "str::from(major)" }],
+}
+"#,
+    );
+
+    let tool = StampVerTool::new();
+    let ScriptRun {
+        root_node,
+        mut run_context,
+        ..
+    } = ScriptRun::new(&tool, &script_file);
+    let mut run = |operation: &str, args: &[&str]| {
+        let args: Vec<String> = args.iter().map(|arg| arg.to_string()).collect();
+
+        tool.run_operation_with_args(
+            Some(operation.to_string()),
+            &args,
+            &root_node,
+            &mut run_context,
+        )
+        .map(|_| {
+            evalexpr::eval_string_with_context(
+                r#"semver::format(major, minor, patch, if(channel == "", "", channel + "." + str::from(channelNum)))"#,
+                &run_context,
+            )
+            .unwrap()
+        })
+    };
+
+    tool.validate_script_file(&root_node).unwrap();
+    assert_eq!(run("nextPrerelease", &["alpha"]).unwrap(), "1.3.0-alpha.1");
+    assert_eq!(run("nextPrerelease", &[]).unwrap(), "1.3.0-alpha.2");
+    assert_eq!(run("nextPrerelease", &["beta"]).unwrap(), "1.3.0-beta.1");
+
+    let err = run("nextPrerelease", &["alpha"]).unwrap_err();
+
+    assert_eq!(
+        err.message,
+        "Cannot move from prerelease channel 'beta' back to 'alpha' (operation 'nextPrerelease' at position 4)"
+    );
+    assert_eq!(err.location.unwrap().line, 9);
+    assert_eq!(run("nextPrerelease", &[]).unwrap(), "1.3.0-beta.2");
+    assert_eq!(run("incrMinor", &[]).unwrap(), "1.4.0-beta.1");
+    assert_eq!(run("nextPrerelease", &[]).unwrap(), "1.4.0-beta.2");
+    assert_eq!(run("incrPatch", &[]).unwrap(), "1.4.1-beta.1");
+    assert_eq!(run("nextPrerelease", &[]).unwrap(), "1.4.1-beta.2");
+    assert_eq!(run("bumpPatch", &[]).unwrap(), "1.4.2-beta.1");
+    assert_eq!(run("promote", &[]).unwrap(), "1.4.2-rc.1");
+    assert!(
+        run("promote", &[])
+            .unwrap_err()
+            .message
+            .contains("last channel")
+    );
+    assert!(
+        run("nextPrerelease", &["gamma"])
+            .unwrap_err()
+            .message
+            .contains("Unknown prerelease channel 'gamma'")
+    );
+    assert_eq!(run("release", &[]).unwrap(), "1.4.2");
+    assert!(
+        run("release", &[])
+            .unwrap_err()
+            .message
+            .contains("already released")
+    );
+    assert!(
+        run("incrMinor", &["beta"])
+            .unwrap_err()
+            .message
+            .contains("does not take arguments")
+    );
+}
+
+#[test]
+fn test_date_functions() {
+    let (_temp_dir, script_file) = write_script(
+        r#"{
+  vars: { tz: "UTC" },
+  calcVars: {},
+  operations: {},
+  targets: [],
+}
+"#,
+    );
+
+    let tool = StampVerTool::new();
+    let ScriptRun { run_context, .. } = ScriptRun::new(&tool, &script_file);
+    let eval = |expression: &str| evalexpr::eval_with_context(expression, &run_context);
+
+    for expression in [
+        r#"now::format("%Y") == str::from(now::year)"#,
+        r#"now::dayOfYear >= 1 && now::dayOfYear <= 366"#,
+        r#"now::week >= 1 && now::week <= 53"#,
+        r#"now::hour < 24 && now::minute < 60 && now::second < 61"#,
+        r#"now::timestamp > 1700000000"#,
+        r#"date::daysSince(now::format("%Y-%m-%d")) == 0"#,
+        r#"date::daysSince("2020-01-01") > 2000"#,
+    ] {
+        assert_eq!(
+            eval(expression).unwrap(),
+            evalexpr::Value::Boolean(true),
+            "{}",
+            expression
+        );
+    }
+
+    assert_eq!(
+        eval(r#"date::daysBetween("2020-01-01", "2021-01-01")"#).unwrap(),
+        evalexpr::Value::Int(366)
+    );
+    assert_eq!(
+        eval(r#"date::daysBetween("2020-03-01", "2020-02-01")"#).unwrap(),
+        evalexpr::Value::Int(-29)
+    );
+    assert!(
+        eval(r#"date::daysSince("2020-13-01")"#)
+            .unwrap_err()
+            .to_string()
+            .contains("'2020-13-01' is not a valid date")
+    );
+}
+
+#[test]
+fn test_clock_and_time_zone() {
+    let (_temp_dir, script_file) = write_script(
+        r#"{
+  vars: { build: 0, tz: "America/Los_Angeles" },
+  calcVars: {},
+  operations: { setBuild: "build = now::year * 10000 + now::month * 100 + now::day" },
+  targets: [{ description: "Stamp", files: ["stamp.txt"], write: 'now::format("%Y-%m-%dT%H:%M") + " " + tz' }],
+}
+"#,
+    );
+
+    let now: jiff::Timestamp = "2024-12-31T23:30:00Z".parse().unwrap();
+    let run = |tool: StampVerTool| {
+        let mut script = ScriptRun::new(&tool, &script_file);
+
+        script.run_operation("setBuild");
+        script.process_targets(true).unwrap();
+        tool.update_script_file(
+            &script.script_file,
+            script.content,
+            &script.root_node,
+            &script.run_context,
+            true,
+        )
+        .unwrap()
+        .diff
+        .unwrap()
+    };
+
+    let diff = run(StampVerTool::new().with_now(now));
+
+    assert!(diff.contains("+  vars: { build: 20241231, tz: \"America/Los_Angeles\" },"));
+
+    let diff = run(StampVerTool::new()
+        .with_now(now)
+        .with_time_zone(jiff::tz::TimeZone::get("Asia/Tokyo").unwrap()));
+
+    assert!(diff.contains("+  vars: { build: 20250101, tz: \"America/Los_Angeles\" },"));
+
+    let output = stampver(&script_file, &["-d", "setBuild"])
+        .env("SOURCE_DATE_EPOCH", "1735687800")
+        .run()
+        .unwrap();
+    let stdout = String::from_utf8(output.stdout).unwrap();
+
+    assert!(output.status.success());
+    assert!(
+        stdout.contains("+2024-12-31T15:30 America/Los_Angeles"),
+        "{}",
+        stdout
+    );
+
+    let output = stampver(
+        &script_file,
+        &[
+            "-d",
+            "--now",
+            "2025-06-01T12:00:00Z",
+            "--tz",
+            "UTC",
+            "setBuild",
+        ],
+    )
+    .env("SOURCE_DATE_EPOCH", "1735687800")
+    .run()
+    .unwrap();
+    let stdout = String::from_utf8(output.stdout).unwrap();
+
+    assert!(output.status.success());
+    assert!(stdout.contains("+2025-06-01T12:00 UTC"), "{}", stdout);
+    assert!(stdout.contains("+  vars: { build: 20250601, tz: \"America/Los_Angeles\" },"));
+}
+
+#[test]
+fn test_git_functions() {
+    let (temp_dir, script_file) = write_script(
+        r#"{
+  vars: { tz: "UTC" },
+  calcVars: {},
+  operations: {},
+  targets: [],
+}
+"#,
+    );
+    // The repository containing the script file is read, not the one containing the current directory.
+    let tool = StampVerTool::new();
+    let ScriptRun { run_context, .. } = ScriptRun::new(&tool, &script_file);
+    let eval = |expression: &str| evalexpr::eval_with_context(expression, &run_context);
+
+    assert!(
+        eval("git::sha()")
+            .unwrap_err()
+            .to_string()
+            .contains("is not in a git repository")
+    );
+
+    let repo = GitRepo::init(temp_dir.path());
+
+    repo.git(&["add", "version.json5"]);
+    repo.commit("First");
+    repo.git(&["tag", "v1.0.0"]);
+    fs::write(temp_dir.path().join("other.txt"), "other\n").unwrap();
+    repo.git(&["add", "other.txt"]);
+    repo.commit("Second");
+
+    let sha = repo.git(&["rev-parse", "HEAD"]);
+
+    assert_eq!(
+        eval("git::sha()").unwrap(),
+        evalexpr::Value::from(sha.clone())
+    );
+    assert!(sha.starts_with(&eval("git::shortSha()").unwrap().as_string().unwrap()));
+    assert_eq!(
+        eval("git::branch()").unwrap(),
+        evalexpr::Value::from("main")
+    );
+    assert_eq!(eval("git::commitCount()").unwrap(), evalexpr::Value::Int(2));
+    assert_eq!(
+        eval("git::lastTag()").unwrap(),
+        evalexpr::Value::from("v1.0.0")
+    );
+    assert!(
+        eval("git::describe()")
+            .unwrap()
+            .as_string()
+            .unwrap()
+            .starts_with("v1.0.0-1-g")
+    );
+    assert_eq!(
+        eval("git::isDirty()").unwrap(),
+        evalexpr::Value::Boolean(false)
+    );
+
+    fs::write(temp_dir.path().join("other.txt"), "changed\n").unwrap();
+    assert_eq!(
+        eval("git::isDirty()").unwrap(),
+        evalexpr::Value::Boolean(true)
+    );
+
+    let other_dir = tempfile::tempdir().unwrap();
+    let other_repo = GitRepo::init(other_dir.path());
+
+    other_repo.commit("Other");
+
+    let tool = StampVerTool::new().with_repo_dir(other_dir.path());
+    let ScriptRun { run_context, .. } = ScriptRun::new(&tool, &script_file);
+
+    assert_eq!(
+        evalexpr::eval_with_context("git::sha()", &run_context).unwrap(),
+        evalexpr::Value::from(other_repo.git(&["rev-parse", "HEAD"]))
+    );
+}
+
+#[test]
+fn test_release_commit_and_tag() {
+    let (temp_dir, script_file) = write_script(
+        r#"{
+  vars: { major: 1, minor: 0, patch: 0, tz: "UTC" },
+  calcVars: {},
+  operations: { incrPatch: "patch += 1" },
+  release: {
+    tag: '"v" + str::from(major) + "." + str::from(minor) + "." + str::from(patch)',
+    message: '"Release " + str::from(major) + "." + str::from(minor) + "." + str::from(patch)',
+  },
+  targets: [
+    {
+      description: "Version file",
+      files: ["version.txt"],
+      write: 'str::from(major) + "." + str::from(minor) + "." + str::from(patch)',
+    },
+  ],
+}
+"#,
+    );
+    let repo = GitRepo::init(temp_dir.path());
+    let release = || {
+        repo.run(stampver(
+            &script_file,
+            &["-u", "--commit", "--tag", "incrPatch"],
+        ))
+    };
+
+    fs::write(temp_dir.path().join("version.txt"), "1.0.0").unwrap();
+    fs::write(temp_dir.path().join("other.txt"), "other\n").unwrap();
+    repo.git(&["add", "."]);
+    repo.commit("First");
+    fs::write(temp_dir.path().join("other.txt"), "changed\n").unwrap();
+
+    let output = release();
+
+    assert!(
+        output.status.success(),
+        "{}",
+        String::from_utf8(output.stderr).unwrap()
+    );
+    assert_eq!(repo.git(&["log", "-1", "--format=%s"]), "Release 1.0.1");
+    assert_eq!(
+        repo.git(&["show", "--name-only", "--format=", "HEAD"]),
+        "version.json5\nversion.txt"
+    );
+    assert_eq!(repo.git(&["cat-file", "-t", "v1.0.1"]), "tag");
+    assert_eq!(repo.git(&["status", "--porcelain"]), "M other.txt");
+
+    repo.git(&["tag", "v1.0.2"]);
+
+    let output = release();
+    let stderr = String::from_utf8(output.stderr).unwrap();
+
+    assert!(!output.status.success());
+    assert!(stderr.contains("Tag 'v1.0.2' already exists"), "{}", stderr);
+    assert_eq!(
+        fs::read_to_string(temp_dir.path().join("version.txt")).unwrap(),
+        "1.0.1"
+    );
+    assert_eq!(repo.git(&["log", "-1", "--format=%s"]), "Release 1.0.1");
+}
+
+#[test]
+fn test_auto_operation() {
+    let (temp_dir, script_file) = write_script(
+        r#"{
+  vars: { major: 1, minor: 2, patch: 3, tz: "UTC" },
+  calcVars: {},
+  operations: {
+    incrMajor: "major += 1; minor = 0; patch = 0",
+    incrMinor: "minor += 1; patch = 0",
+    incrPatch: "patch += 1",
+  },
+  auto: { major: "incrMajor", minor: "incrMinor", patch: "incrPatch", tagMatch: "v*" },
+  targets: [{ description: "Version", files: ["version.txt"], write: "str::from(major)" }],
+}
+"#,
+    );
+    let repo = GitRepo::init(temp_dir.path());
+    let auto = || {
+        let output = repo.run(stampver(&script_file, &["-d", "auto"]));
+
+        (
+            String::from_utf8(output.stdout).unwrap(),
+            String::from_utf8(output.stderr).unwrap(),
+        )
+    };
+
+    repo.commit("feat: Not counted because it is before the tag");
+    repo.git(&["tag", "v1.2.3"]);
+    repo.commit("docs: Update the README");
+
+    let (_, stderr) = auto();
+
+    assert!(
+        stderr.contains(
+            "None of the 1 commit(s) since tag 'v1.2.3' are a feature, fix or breaking change"
+        ),
+        "{}",
+        stderr
+    );
+
+    repo.commit("fix: Fix a bug");
+    repo.commit("feat(ui): Add a button");
+
+    let (stdout, stderr) = auto();
+
+    assert!(
+        stderr.contains("'Add a button' needs a minor release"),
+        "{}",
+        stderr
+    );
+    assert!(stderr.contains(
+        "Chose a minor release with operation 'incrMinor' from 3 commit(s) since tag 'v1.2.3'"
+    ));
+    assert!(stdout.contains("+  vars: { major: 1, minor: 3, patch: 0, tz: \"UTC\" },"));
+
+    repo.commit("refactor: Change the API\n\nBREAKING CHANGE: The API is different");
+
+    let (stdout, stderr) = auto();
+
+    assert!(
+        stderr.contains("Chose a major release with operation 'incrMajor'"),
+        "{}",
+        stderr
+    );
+    assert!(stdout.contains("+  vars: { major: 2, minor: 0, patch: 0, tz: \"UTC\" },"));
+}
+
+#[test]
+fn test_changelog_target() {
+    let (temp_dir, script_file) = write_script(
+        r#"{
+  vars: { major: 1, minor: 2, patch: 3, tz: "UTC" },
+  calcVars: {},
+  operations: { incrMinor: "minor += 1; patch = 0" },
+  targets: [
+    {
+      description: "Changelog",
+      files: ["CHANGELOG.md"],
+      changelog: {
+        version: 'semver::format(major, minor, patch, "")',
+        tagMatch: "v*",
+      },
+    },
+  ],
+}
+"#,
+    );
+    let changelog_file = temp_dir.path().join("CHANGELOG.md");
+    let repo = GitRepo::init(temp_dir.path());
+    let run = |operation: &str| {
+        let output = repo.run(stampver(
+            &script_file,
+            &["-u", "--now", "2024-05-06T12:00:00Z", operation],
+        ));
+
+        (
+            output.status.success(),
+            String::from_utf8(output.stderr).unwrap(),
+        )
+    };
+
+    fs::write(
+        &changelog_file,
+        "# Changelog\n\n## 1.2.3 - 2024-01-01\n\n- Initial release\n",
+    )
+    .unwrap();
+    repo.commit("feat: Not listed because it is before the tag");
+    repo.git(&["tag", "v1.2.3"]);
+    repo.commit("fix: Fix a bug");
+    repo.commit("docs: Update the README");
+    repo.commit("feat(ui): Add a button");
+    repo.commit("feat!: Remove the old API");
+
+    let (success, stderr) = run("incrMinor");
+
+    assert!(success, "{}", stderr);
+
+    let changelog = fs::read_to_string(&changelog_file).unwrap();
+    let sha = |rev: &str| repo.git(&["rev-parse", "--short=7", rev]);
+
+    assert_eq!(
+        changelog,
+        format!(
+            "# Changelog\n\n\
+             ## 1.3.0 - 2024-05-06\n\n\
+             ### Breaking Changes\n\n\
+             - Remove the old API ({})\n\n\
+             ### Features\n\n\
+             - **ui:** Add a button ({})\n\n\
+             ### Bug Fixes\n\n\
+             - Fix a bug ({})\n\n\
+             ## 1.2.3 - 2024-01-01\n\n\
+             - Initial release\n",
+            sha("HEAD"),
+            sha("HEAD~1"),
+            sha("HEAD~3")
+        )
+    );
+
+    let output = repo.run(stampver(&script_file, &["check"]));
+
+    assert!(
+        output.status.success(),
+        "{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+
+    fs::remove_file(&changelog_file).unwrap();
+
+    let (success, stderr) = run("incrMinor");
+
+    assert!(success, "{}", stderr);
+    assert!(
+        fs::read_to_string(&changelog_file)
+            .unwrap()
+            .starts_with("## 1.4.0 - 2024-05-06\n\n### Breaking Changes\n"),
+    );
+}
+
+#[test]
+fn test_env_functions() {
+    let (_temp_dir, script_file) = write_script(
+        r#"{
+  vars: { build: 0, tz: "UTC" },
+  calcVars: {},
+  envVars: ["STAMPVER_TEST_RUN", "STAMPVER_TEST_CHANNEL", "STAMPVER_TEST_UNSET"],
+  operations: {
+    setBuild: 'build = env::get_or("STAMPVER_TEST_RUN", 0) + env::get_or("STAMPVER_TEST_UNSET", 1)',
+    setBadBuild: 'build = env::get_or("STAMPVER_TEST_CHANNEL", 0)',
+    getUnset: 'build = env::get("STAMPVER_TEST_UNSET")',
+    getUnlisted: 'build = env::get("HOME")',
+  },
+  targets: [
+    {
+      description: "Build",
+      files: ["build.txt"],
+      write: 'env::get("STAMPVER_TEST_CHANNEL") + "." + str::from(build)',
+    },
+  ],
+}
+"#,
+    );
+    let run = |operation: &str| {
+        let output = stampver(&script_file, &["-d", operation])
+            .env("STAMPVER_TEST_RUN", "42")
+            .env("STAMPVER_TEST_CHANNEL", "beta")
+            .env_remove("STAMPVER_TEST_UNSET")
+            .run()
+            .unwrap();
+
+        (
+            String::from_utf8(output.stdout).unwrap(),
+            String::from_utf8(output.stderr).unwrap(),
+        )
+    };
+
+    let (stdout, stderr) = run("setBuild");
+
+    assert!(stdout.contains("+beta.43"), "{}", stderr);
+    assert!(stdout.contains("+  vars: { build: 43, tz: \"UTC\" },"));
+
+    let (_, stderr) = run("setBadBuild");
+
+    assert!(
+        stderr.contains(
+            "Environment variable 'STAMPVER_TEST_CHANNEL' value 'beta' is not an integer"
+        )
+    );
+
+    let (_, stderr) = run("getUnset");
+
+    assert!(stderr.contains("Environment variable 'STAMPVER_TEST_UNSET' is not set"));
+
+    let (_, stderr) = run("getUnlisted");
+
+    assert!(stderr.contains("Environment variable 'HOME' is not listed in 'envVars'"));
+}
+
+#[test]
+fn test_var_overrides() {
+    let (temp_dir, script_file) = write_script(
+        r#"{
+  vars: { patch: 3, buildType: "test", tz: "UTC" },
+  calcVars: { build: "patch * 100" },
+  operations: { incrPatch: "patch += 1" },
+  targets: [
+    {
+      description: "Version",
+      files: ["version.txt"],
+      write: 'str::from(patch) + "-" + buildType + "+" + str::from(build)',
+    },
+  ],
+}
+"#,
+    );
+    let run = |args: &[&str]| {
+        let output = stampver(&script_file, &[&["-u", "incrPatch"], args].concat())
+            .run()
+            .unwrap();
+
+        (
+            fs::read_to_string(temp_dir.path().join("version.txt")).unwrap_or_default(),
+            fs::read_to_string(&script_file).unwrap(),
+            String::from_utf8(output.stderr).unwrap(),
+        )
+    };
+
+    let (version, script, stderr) = run(&["--set", "buildType=beta", "--set-int", "build=42"]);
+
+    assert_eq!(version, "4-beta+42", "{}", stderr);
+    assert!(script.contains("vars: { patch: 4, buildType: \"test\", tz: \"UTC\" },"));
+
+    let (version, script, stderr) = run(&["--set", "patch=9", "--persist-set"]);
+
+    assert_eq!(version, "10-test+900", "{}", stderr);
+    assert!(script.contains("vars: { patch: 10, buildType: \"test\", tz: \"UTC\" },"));
+
+    let (_, _, stderr) = run(&["--set", "patch=beta"]);
+
+    assert!(stderr.contains("Value 'beta' for var 'patch' must be an integer"));
+
+    let (_, _, stderr) = run(&["--set-str", "patch=1"]);
+
+    assert!(stderr.contains("Override for var 'patch' must be an integer"));
+
+    let (_, _, stderr) = run(&["--set", "minor=1"]);
+
+    assert!(
+        stderr.contains("Cannot override var 'minor' because it is not in 'vars' or 'calcVars'")
+    );
+
+    let (_, _, stderr) = run(&["--set", "patch"]);
+
+    assert!(stderr.contains("'--set' expects NAME=VALUE, not 'patch'"));
+}
+
+#[test]
+fn test_multiple_operations() {
+    let (_temp_dir, script_file) = write_script(
+        r#"{
+  vars: { major: 1, minor: 2, patch: 3, build: 0, buildType: "test", channel: "", channelNum: 0, tz: "UTC" },
+  calcVars: {},
+  operations: {
+    stampBuild: "build = build + 1",
+    incrMinor: 'minor += 1; patch = 0; op::run("stampBuild")',
+    incrPatch: 'patch += 1; op::run( "stampBuild" ); patch += 10',
+    setBeta: 'buildType = "beta"',
+    setQuoted: 'buildType = "op::run(\\"setBeta\\")"',
+    broken: "build = unknown + 1",
+    callBroken: 'op::run("stampBuild"); op::run("broken")',
+  },
+  prerelease: { channels: ["alpha", "beta"], channelVar: "channel", numberVar: "channelNum" },
+  targets: [{ description: "Version", files: ["version.txt"], write: "str::from(major)" }],
+}
+"#,
+    );
+
+    let tool = StampVerTool::new();
+    let (_, root_node, _) = tool.read_script_file(script_file).unwrap();
+    let run = |words: &[&str]| {
+        let mut run_context = tool.create_run_context(&root_node).unwrap();
+        let words: Vec<String> = words.iter().map(|word| word.to_string()).collect();
+
+        tool.parse_operations(&root_node, &words)
+            .and_then(|operations| tool.run_operations(&operations, &root_node, &mut run_context))
+            .map(|_| {
+                evalexpr::eval_string_with_context(
+                    r#"semver::format(major, minor, patch, channel) + "+" + str::from(build) + "." + buildType"#,
+                    &run_context,
+                )
+                .unwrap()
+            })
+    };
+
+    tool.validate_script_file(&root_node).unwrap();
+    assert_eq!(run(&["incrMinor", "setBeta"]).unwrap(), "1.3.0+1.beta");
+    assert_eq!(run(&["incrPatch"]).unwrap(), "1.2.14+1.test");
+    assert_eq!(
+        run(&["setQuoted"]).unwrap(),
+        r#"1.2.3+0.op::run("setBeta")"#
+    );
+
+    let error = run(&["callBroken"]).unwrap_err();
+
+    assert!(error.message.contains("unknown"), "{}", error);
+    assert_eq!(error.location.map(|location| location.line), Some(10));
+    assert_eq!(
+        run(&["incrMinor", "nextPrerelease", "alpha", "incrMinor"]).unwrap(),
+        "1.4.0-alpha+2.test"
+    );
+    assert_eq!(
+        run(&["incrMinor", "extra"]).unwrap_err().message,
+        "Operation 'incrMinor' does not take arguments"
+    );
+    assert_eq!(
+        run(&["unknown", "incrMinor"]).unwrap_err().message,
+        "Operation 'unknown' not found"
+    );
+    assert!(
+        run(&[])
+            .unwrap_err()
+            .message
+            .starts_with("Specify a valid operation, one of 'stampBuild', 'incrMinor'")
+    );
+
+    let check_operations = |operations: &str| {
+        let root_node = json5_nodes::parse(&format!(
+            r#"{{ vars: {{ tz: "UTC" }}, operations: {}, targets: [{{ description: "Version", files: ["version.txt"], write: "1" }}] }}"#,
+            operations
+        ))
+        .unwrap();
+        tool.validate_script_file(&root_node).unwrap_err().message
+    };
+
+    assert_eq!(
+        check_operations(r#"{ a: 'op::run("b")', b: 'op::run("c")', c: 'op::run("a")' }"#),
+        "Operation 'a' calls itself through 'a' -> 'b' -> 'c' -> 'a'"
+    );
+    assert_eq!(
+        check_operations(r#"{ a: 'op::run("missing")' }"#),
+        "Operation 'a' calls operation 'missing', which is not in 'operations'"
+    );
+    assert_eq!(
+        check_operations(r#"{ a: 'op::run("a")' }"#),
+        "Operation 'a' calls itself through 'a' -> 'a'"
+    );
+
+    for operations in [
+        r#"{ a: 'if(true, op::run("b"), 1)', b: "" }"#,
+        r#"{ a: 'x = op::run("b")', b: "" }"#,
+        r#"{ a: 'op::run(name)', b: "" }"#,
+    ] {
+        assert!(
+            check_operations(operations).starts_with("'op::run' must be a whole statement"),
+            "{}",
+            operations
+        );
+    }
+}
+
+#[test]
+fn test_operation_params() {
+    let (_temp_dir, script_file) = write_script(
+        r#"{
+  vars: { build: 0, buildType: "test", tz: "UTC" },
+  calcVars: {},
+  operations: {
+    setChannel: { params: ["channel"], expr: "buildType = channel" },
+    setBuild: {
+      params: [{ name: "number", type: "integer" }, { name: "offset", type: "integer", default: 100 }],
+      expr: "build = number + offset",
+    },
+  },
+  targets: [{ description: "Version", files: ["version.txt"], write: "buildType" }],
+}
+"#,
+    );
+
+    let tool = StampVerTool::new();
+    let (_, root_node, _) = tool.read_script_file(script_file).unwrap();
+    let run = |words: &[&str]| {
+        let mut run_context = tool.create_run_context(&root_node).unwrap();
+        let words: Vec<String> = words.iter().map(|word| word.to_string()).collect();
+
+        tool.parse_operations(&root_node, &words)
+            .and_then(|operations| tool.run_operations(&operations, &root_node, &mut run_context))
+            .map(|_| {
+                assert!(evalexpr::eval_with_context("channel", &run_context).is_err());
+                evalexpr::eval_string_with_context(
+                    r#"buildType + "." + str::from(build)"#,
+                    &run_context,
+                )
+                .unwrap()
+            })
+    };
+
+    tool.validate_script_file(&root_node).unwrap();
+    assert_eq!(
+        run(&["setChannel", "channel=beta", "setBuild", "number=5"]).unwrap(),
+        "beta.105"
+    );
+    assert_eq!(
+        run(&["setBuild", "offset=0", "number=7"]).unwrap(),
+        "test.7"
+    );
+    assert_eq!(
+        run(&["setBuild"]).unwrap_err().message,
+        "Operation 'setBuild' is missing parameter(s) 'number'"
+    );
+    assert_eq!(
+        run(&["setBuild", "number=seven"]).unwrap_err().message,
+        "Parameter 'number' of operation 'setBuild' must be an integer, not 'seven'"
+    );
+    assert_eq!(
+        run(&["setChannel", "beta"]).unwrap_err().message,
+        "Arguments for operation 'setChannel' must be name=value, not 'beta'"
+    );
+    assert_eq!(
+        run(&["setChannel", "name=beta"]).unwrap_err().message,
+        "Operation 'setChannel' has no parameter 'name', expected 'channel'"
+    );
+
+    let check_operations = |operations: &str| {
+        let root_node = json5_nodes::parse(&format!(
+            r#"{{ vars: {{ build: 0, tz: "UTC" }}, operations: {}, targets: [{{ description: "Version", files: ["version.txt"], write: "1" }}] }}"#,
+            operations
+        ))
+        .unwrap();
+
+        tool.validate_script_file(&root_node).unwrap_err().message
+    };
+
+    assert_eq!(
+        check_operations(r#"{ a: { params: ["build"], expr: "build = 1" } }"#),
+        "Parameter 'build' has the same name as a var or calculated var"
+    );
+    assert_eq!(
+        check_operations(
+            r#"{ a: { params: [{ name: "n", type: "integer", default: "1" }], expr: "" } }"#
+        ),
+        "'default' for parameter 'n' must be an integer"
+    );
+    assert_eq!(
+        check_operations(r#"{ a: { params: [{ name: "n", type: "number" }], expr: "" } }"#),
+        "'type' entry must be 'string', 'integer', 'float' or 'boolean'"
+    );
+    assert_eq!(
+        check_operations(r#"{ a: { params: ["n"], expr: "" }, b: 'op::run("a")' }"#),
+        "Operation 'b' calls operation 'a', which takes parameters"
+    );
+}
+
+#[test]
+fn test_when_expressions() {
+    let (temp_dir, script_file) = write_script(
+        r#"{
+  vars: { major: 1, buildType: "test", tz: "UTC" },
+  calcVars: {},
+  operations: { incrMajor: "major += 1", setProd: 'buildType = "prod"' },
+  targets: [
+    {
+      description: "Production version",
+      files: ["prod.txt"],
+      when: 'buildType == "prod"',
+      write: "str::from(major)",
+    },
+    {
+      description: "Version",
+      files: ["version.txt"],
+      updates: [
+        { search: "^(?P<begin>major = )\\d+$", replace: "begin + str::from(major)" },
+        {
+          search: "^(?P<begin>bumped = ).*$",
+          replace: "begin + op::name",
+          when: 'contains(op::names, "incrMajor")',
+        },
+      ],
+    },
+  ],
+}
+"#,
+    );
+    let run = |args: &[&str]| {
+        let output = stampver(&script_file, &[&["-d"], args].concat())
+            .run()
+            .unwrap();
+
+        (
+            String::from_utf8(output.stdout).unwrap(),
+            String::from_utf8(output.stderr).unwrap(),
+        )
+    };
+
+    fs::write(
+        temp_dir.path().join("version.txt"),
+        "major = 1\nbumped = no\n",
+    )
+    .unwrap();
+
+    let (stdout, stderr) = run(&["incrMajor"]);
+
+    assert!(
+        stderr.contains(
+            "Target 'Production version' is skipped because 'when' expression 'buildType == \"prod\"' is false"
+        ),
+        "{}",
+        stderr
+    );
+    assert!(!stdout.contains("prod.txt"));
+    assert!(stdout.contains("+major = 2"));
+    assert!(stdout.contains("+bumped = incrMajor"));
+
+    let (stdout, stderr) = run(&["setProd"]);
+
+    assert!(stdout.contains("+++ b/prod.txt"), "{}", stderr);
+    assert!(!stdout.contains("+bumped"));
+    assert!(stderr.contains(
+        "Skipped '^(?P<begin>bumped = ).*$' because 'when' expression 'contains(op::names, \"incrMajor\")' is false"
+    ));
+}
+
+#[test]
+fn test_extends_and_include() {
+    let temp_dir = tempfile::tempdir().unwrap();
+    let shared_dir = temp_dir.path().join("shared");
+    let script_file = temp_dir.path().join("version.json5");
+    let run = |operation: &str| {
+        let output = stampver(&script_file, &["-u", operation]).run().unwrap();
+
+        (
+            fs::read_to_string(temp_dir.path().join("version.txt")).unwrap_or_default(),
+            String::from_utf8(output.stderr).unwrap(),
+        )
+    };
+
+    fs::create_dir(&shared_dir).unwrap();
+    fs::write(
+        shared_dir.join("base.json5"),
+        r#"{
+  vars: { major: 1, minor: 2, tz: "UTC" },
+  calcVars: {},
+  operations: { incrMinor: "minor += 1", incrMajor: "major += 1; minor = 0" },
+  targets: [
+    { description: "Version", files: ["version.txt"], write: '"base"' },
+  ],
+}
+"#,
+    )
+    .unwrap();
+    fs::write(
+        shared_dir.join("ops.json5"),
+        r#"{
+  operations: { incrPatch: "patch += 1" },
+}
+"#,
+    )
+    .unwrap();
+    fs::write(
+        &script_file,
+        r#"{
+  extends: "shared/base.json5",
+  include: ["shared/ops.json5"],
+  vars: { patch: 5 },
+  operations: { incrMinor: "minor += 1; patch = 0" },
+  targets: [
+    {
+      description: "Version",
+      files: ["version.txt"],
+      write: 'str::from(major) + "." + str::from(minor) + "." + str::from(patch)',
+    },
+  ],
+}
+"#,
+    )
+    .unwrap();
+
+    let (version, stderr) = run("incrPatch");
+
+    assert_eq!(version, "1.2.6", "{}", stderr);
+    assert!(
+        fs::read_to_string(&script_file)
+            .unwrap()
+            .contains("vars: { patch: 6 },")
+    );
+
+    let (version, stderr) = run("incrMinor");
+
+    assert_eq!(version, "1.3.0", "{}", stderr);
+    assert!(
+        fs::read_to_string(shared_dir.join("base.json5"))
+            .unwrap()
+            .contains("vars: { major: 1, minor: 3, tz: \"UTC\" },")
+    );
+    assert!(
+        fs::read_to_string(&script_file)
+            .unwrap()
+            .contains("vars: { patch: 0 },")
+    );
+
+    fs::write(
+        shared_dir.join("ops.json5"),
+        r#"{
+  operations: {
+    incrPatch: { params: [{ name: "by", type: "number" }], expr: "patch += by" },
+  },
+}
+"#,
+    )
+    .unwrap();
+
+    let (_, stderr) = run("incrPatch");
+
+    assert!(
+        stderr.contains(&format!(
+            "{} (3:47): 'type' entry must be",
+            shared_dir
+                .join("ops.json5")
+                .canonicalize()
+                .unwrap()
+                .display()
+        )),
+        "{}",
+        stderr
+    );
+
+    fs::write(
+        shared_dir.join("ops.json5"),
+        r#"{
+  operations: { incrMajor: "major += 2" },
+}
+"#,
+    )
+    .unwrap();
+
+    let (_, stderr) = run("incrMajor");
+
+    assert!(stderr.contains("'operations' entry 'incrMajor' is defined in more than one file"));
+
+    fs::write(
+        shared_dir.join("ops.json5"),
+        r#"{
+  include: ["../version.json5"],
+}
+"#,
+    )
+    .unwrap();
+
+    let (_, stderr) = run("incrMajor");
+
+    assert!(stderr.contains("includes itself"), "{}", stderr);
+}
+
+#[test]
+fn test_extends_outside_script_dir() {
+    let temp_dir = tempfile::tempdir().unwrap();
+    let app_dir = temp_dir.path().join("app");
+    let shared_dir = temp_dir.path().join("shared");
+    let script_file = app_dir.join("version.json5");
+    let patch_file = temp_dir.path().join("version.patch");
+
+    fs::create_dir(&app_dir).unwrap();
+    fs::create_dir(&shared_dir).unwrap();
+    fs::write(
+        shared_dir.join("base.json5"),
+        r#"{
+  vars: { major: 1, minor: 2, tz: "UTC" },
+  calcVars: {},
+  operations: { incrMinor: "minor += 1" },
+  targets: [
+    { description: "Version", files: ["version.txt"], write: 'str::from(major) + "." + str::from(minor)' },
+  ],
+}
+"#,
+    )
+    .unwrap();
+    fs::write(&script_file, "{\n  extends: \"../shared/base.json5\",\n}\n").unwrap();
+
+    let output = stampver(
+        &script_file,
+        &["--patch", patch_file.to_str().unwrap(), "incrMinor"],
+    )
+    .run()
+    .unwrap();
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    let patch = fs::read_to_string(&patch_file).unwrap();
+
+    assert!(output.status.success(), "{}", stderr);
+    assert!(patch.contains("+++ b/app/version.txt"), "{}", patch);
+    assert!(patch.contains("--- a/shared/base.json5"), "{}", patch);
+    assert!(patch.contains("+++ b/shared/base.json5"), "{}", patch);
+
+    let output = cmd!("git", "apply", &patch_file)
+        .dir(temp_dir.path())
+        .stderr_capture()
+        .unchecked()
+        .run()
+        .unwrap();
+
+    assert!(
+        output.status.success(),
+        "{}",
+        String::from_utf8(output.stderr).unwrap()
+    );
+    assert_eq!(
+        fs::read_to_string(app_dir.join("version.txt")).unwrap(),
+        "1.3"
+    );
+    assert!(
+        fs::read_to_string(shared_dir.join("base.json5"))
+            .unwrap()
+            .contains("vars: { major: 1, minor: 3, tz: \"UTC\" },")
+    );
+
+    fs::write(
+        shared_dir.join("base.json5"),
+        r#"{
+  vars: { major: 1, minor: 2, tz: "UTC" },
+  calcVars: {},
+  operations: { incrMinor: "minor += 1" },
+  targets: [{ description: "Version", files: ["version.txt"], write: 1 }],
+}
+"#,
+    )
+    .unwrap();
+
+    let tool = StampVerTool::new();
+    let (_, root_node, _) = tool.read_script_file(script_file.clone()).unwrap();
+    let error = tool.validate_script_file(&root_node).unwrap_err();
+
+    assert_eq!(
+        error.script_file,
+        Some(shared_dir.join("base.json5").canonicalize().unwrap())
+    );
+    assert_eq!(error.location.map(|location| location.line), Some(5));
+}
+
+#[test]
+fn test_error_files_with_the_same_location() {
+    let temp_dir = tempfile::tempdir().unwrap();
+    let base_file = temp_dir.path().join("base.json5");
+    let script_file = temp_dir.path().join("version.json5");
+
+    fs::write(
+        &base_file,
+        r#"{
+  vars: { major: 1, minor: 2, channel: "", channelNum: 0, tz: "UTC" },
+  calcVars: {},
+  operations: {},
+  prerelease: { channels: ["beta"], channelVar: "channel", numberVar: "channelNum" },
+  targets: [{ description: "Version", files: ["version.txt"], write: "str::from(major)" }],
+}
+"#,
+    )
+    .unwrap();
+    fs::write(
+        &script_file,
+        r#"{
+  extends: "base.json5",
+  "$sources": "kept",
+  vars: {},
+  operations: { incrMinor: "minor += 1" },
+}
+"#,
+    )
+    .unwrap();
+
+    let tool = StampVerTool::new();
+    let ScriptRun {
+        root_node,
+        mut run_context,
+        script_file,
+        ..
+    } = ScriptRun::new(&tool, &script_file);
+
+    tool.validate_script_file(&root_node).unwrap();
+    assert!(matches!(
+        &root_node,
+        JsonNode::Object(entries, _)
+            if matches!(entries.get("$sources"), Some(JsonNode::String(value, _)) if value == "kept")
+    ));
+
+    // The `prerelease` section of the base file and the `operations` section of the script file are both
+    // at line 5, column 15.
+    let error = tool
+        .run_operation(Some("release".to_string()), &root_node, &mut run_context)
+        .unwrap_err();
+
+    assert!(error.message.contains("already released"), "{}", error);
+    assert_eq!(error.script_file, Some(base_file.canonicalize().unwrap()));
+
+    let error = tool
+        .run_operation(Some("incrMajor".to_string()), &root_node, &mut run_context)
+        .unwrap_err();
+
+    assert!(error.message.contains("not found"), "{}", error);
+    assert_eq!(error.script_file, Some(script_file));
+    assert_eq!(
+        error
+            .location
+            .map(|location| (location.line, location.column)),
+        Some((5, 15))
+    );
+}
+
+#[test]
+fn test_components() {
+    let (temp_dir, script_file) = write_script(
+        r#"{
+  vars: { tz: "UTC" },
+  calcVars: { series: 'str::from(major) + ".x"' },
+  operations: { incrMinor: "minor += 1" },
+  components: {
+    lib: {
+      vars: { major: 2, minor: 4 },
+      targets: [{ description: "Library", files: ["lib.txt"], write: 'str::from(minor) + "/" + series' }],
+    },
+    cli: {
+      vars: { major: 0, minor: 9, build: 1 },
+      operations: { incrMinor: "minor += 2" },
+      targets: [{ description: "CLI", files: ["cli.txt"], write: 'str::from(minor) + "/" + series + "+" + str::from(build)' }],
+    },
+  },
+}
+"#,
+    );
+    let run = |args: &[&str]| {
+        let output = stampver(&script_file, &[&["-u"], args].concat())
+            .run()
+            .unwrap();
+
+        (
+            fs::read_to_string(temp_dir.path().join("lib.txt")).unwrap_or_default(),
+            fs::read_to_string(temp_dir.path().join("cli.txt")).unwrap_or_default(),
+            String::from_utf8(output.stderr).unwrap(),
+        )
+    };
+
+    let (lib, cli, stderr) = run(&["incrMinor", "--component", "cli"]);
+
+    assert_eq!((lib.as_str(), cli.as_str()), ("", "11/0.x+1"), "{}", stderr);
+
+    let (lib, cli, stderr) = run(&["incrMinor"]);
+
+    assert_eq!(
+        (lib.as_str(), cli.as_str()),
+        ("5/2.x", "13/0.x+1"),
+        "{}",
+        stderr
+    );
+
+    let script = fs::read_to_string(&script_file).unwrap();
+
+    assert!(script.contains("vars: { major: 2, minor: 5 },"));
+    assert!(script.contains("vars: { major: 0, minor: 13, build: 1 },"));
+
+    let (lib, cli, stderr) = run(&["incrMinor", "--set", "build=5"]);
+
+    assert_eq!(
+        (lib.as_str(), cli.as_str()),
+        ("6/2.x", "15/0.x+5"),
+        "{}",
+        stderr
+    );
+    assert!(
+        fs::read_to_string(&script_file)
+            .unwrap()
+            .contains("vars: { major: 0, minor: 15, build: 1 },")
+    );
+
+    let (_, _, stderr) = run(&["incrMinor", "--component", "lib", "--set", "build=5"]);
+
+    assert!(
+        stderr.contains("Cannot override var 'build' because it is not in 'vars' or 'calcVars'"),
+        "{}",
+        stderr
+    );
+
+    let (_, _, stderr) = run(&["incrMinor", "--component", "docs"]);
+
+    assert!(stderr.contains("Component 'docs' not found, expected one of 'lib', 'cli'"));
+
+    let (_, _, stderr) = run(&["incrMinor", "--commit"]);
+
+    assert!(stderr.contains("'--commit' can only be used with one component"));
 }